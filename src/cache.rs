@@ -0,0 +1,276 @@
+use std::fs::{read_dir, read_to_string, remove_file, write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::lyric::Lyric;
+use crate::provider::{ProviderKind, TrackInfo};
+
+const SECONDS_PER_HOUR: u64 = 60 * 60;
+
+/// Lyrics fetched by online providers, one JSON file per provider and track, stored under
+/// `$XDG_CACHE_HOME/desktop_lyric`.
+pub struct LyricCache {
+    dir: PathBuf,
+    /// Seconds a found lyric is kept.
+    ttl: u64,
+    /// Seconds a "not found" is kept.
+    negative_ttl: u64,
+    /// Bytes the cache is trimmed to.
+    max_size: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub key: String,
+    /// Unix time in seconds.
+    pub fetched_at: u64,
+    /// `None` when the provider had no lyric for the track.
+    pub lyric: Option<Lyric>,
+}
+
+impl LyricCache {
+    /// `None` when the cache is turned off or there is no cache directory.
+    pub fn new(config: &Config) -> Option<Self> {
+        let dir = dirs::cache_dir()?.join("desktop_lyric");
+        Self::with_dir(dir, config)
+    }
+
+    fn with_dir(dir: PathBuf, config: &Config) -> Option<Self> {
+        if config.cache_ttl_hours == 0 {
+            return None;
+        }
+        Some(Self {
+            dir,
+            ttl: config.cache_ttl_hours * SECONDS_PER_HOUR,
+            negative_ttl: config.cache_negative_ttl_hours * SECONDS_PER_HOUR,
+            max_size: config.cache_max_size_mb << 20,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The cached result of `provider` for `track`: `Some(None)` is a remembered "not found".
+    pub fn get(&self, provider: ProviderKind, track: &TrackInfo) -> Option<Option<Lyric>> {
+        let key = cache_key(provider, track)?;
+        let path = self.path(&key);
+        let entry: CacheEntry = serde_json::from_str(&read_to_string(&path).ok()?).ok()?;
+        // Another key with the same hash.
+        if entry.key != key {
+            return None;
+        }
+        if self.is_expired(&entry) {
+            debug!("Cached lyric expired: {}", key);
+            let _ = remove_file(&path);
+            return None;
+        }
+        Some(entry.lyric)
+    }
+
+    /// Remembers what `provider` returned for `track`, then trims the cache.
+    pub fn put(&self, provider: ProviderKind, track: &TrackInfo, lyric: Option<&Lyric>) {
+        let Some(key) = cache_key(provider, track) else {
+            return;
+        };
+        if lyric.is_none() && self.negative_ttl == 0 {
+            return;
+        }
+        let entry = CacheEntry {
+            key,
+            fetched_at: now(),
+            lyric: lyric.cloned(),
+        };
+        let result = std::fs::create_dir_all(&self.dir)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string(&entry).map_err(|e| e.to_string()))
+            .and_then(|v| write(self.path(&entry.key), v).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to cache lyric: {}", e);
+            return;
+        }
+        self.prune();
+    }
+
+    /// Every readable entry with the size of its file, oldest first.
+    pub fn entries(&self) -> Vec<(CacheEntry, u64)> {
+        let mut entries = self
+            .files()
+            .into_iter()
+            .filter_map(|(path, size, _)| {
+                let entry = serde_json::from_str(&read_to_string(path).ok()?).ok()?;
+                Some((entry, size))
+            })
+            .collect::<Vec<(CacheEntry, u64)>>();
+        entries.sort_by_key(|(v, _)| v.fetched_at);
+        entries
+    }
+
+    pub fn is_expired(&self, entry: &CacheEntry) -> bool {
+        let ttl = match entry.lyric {
+            Some(_) => self.ttl,
+            None => self.negative_ttl,
+        };
+        now().saturating_sub(entry.fetched_at) >= ttl
+    }
+
+    /// Removes every entry, returning how many there were.
+    pub fn clear(&self) -> Result<usize, String> {
+        let files = self.files();
+        for (path, _, _) in files.iter() {
+            remove_file(path).map_err(|e| e.to_string())?;
+        }
+        Ok(files.len())
+    }
+
+    /// Drops files older than the TTL, then the oldest ones until the cache fits `max_size`.
+    fn prune(&self) {
+        let ttl = SystemTime::now() - Duration::from_secs(self.ttl);
+        let mut files = self.files();
+        files.retain(|(path, _, modified)| *modified >= ttl || remove_file(path).is_err());
+        files.sort_by_key(|(_, _, modified)| *modified);
+        let mut size: u64 = files.iter().map(|(_, size, _)| size).sum();
+        for (path, file_size, _) in files {
+            if size <= self.max_size {
+                break;
+            }
+            if remove_file(&path).is_ok() {
+                size -= file_size;
+            }
+        }
+    }
+
+    /// Cache files with their size and modification time.
+    fn files(&self) -> Vec<(PathBuf, u64, SystemTime)> {
+        let Ok(dir) = read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        dir.filter_map(|v| v.ok())
+            .filter(|v| v.path().extension().is_some_and(|v| v == "json"))
+            .filter_map(|v| {
+                let metadata = v.metadata().ok()?;
+                Some((v.path(), metadata.len(), metadata.modified().ok()?))
+            })
+            .collect()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", fnv1a(key)))
+    }
+}
+
+/// `<provider>:<artist>/<title>/<seconds>` in lowercase with collapsed whitespace, or
+/// `<provider>:<trackid>` for tracks without a title.
+fn cache_key(provider: ProviderKind, track: &TrackInfo) -> Option<String> {
+    let normalize = |v: &str| v.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let track = if !track.title.trim().is_empty() {
+        format!(
+            "{}/{}/{}",
+            normalize(&track.artists.join(", ")),
+            normalize(&track.title),
+            track.length.map_or(0, |v| v / 1000)
+        )
+    } else {
+        track.track_id.clone()?
+    };
+    Some(format!("{:?}:{}", provider, track).to_lowercase())
+}
+
+/// 64-bit FNV-1a, stable across builds unlike `DefaultHasher`.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |v| v.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(name: &str, max_size: u64) -> LyricCache {
+        let dir =
+            std::env::temp_dir().join(format!("desktop_lyric_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let config: Config = serde_yaml::from_str(include_str!("../config.yaml")).unwrap();
+        let mut cache = LyricCache::with_dir(dir, &config).unwrap();
+        cache.max_size = max_size;
+        cache
+    }
+
+    #[test]
+    fn test_cache() {
+        let cache = cache("cache", 50 << 20);
+        let track = TrackInfo {
+            title: "Song  Title".to_owned(),
+            artists: vec!["Artist".to_owned()],
+            length: Some(200_400),
+            ..Default::default()
+        };
+        assert!(cache.get(ProviderKind::Lrclib, &track).is_none());
+        cache.put(
+            ProviderKind::Lrclib,
+            &track,
+            Some(&Lyric::from_str("[00:01.00]a")),
+        );
+        cache.put(ProviderKind::Netease, &track, None);
+        let same = TrackInfo {
+            title: "song title".to_owned(),
+            length: Some(200_900),
+            ..track.clone()
+        };
+        let lyric = cache.get(ProviderKind::Lrclib, &same).unwrap().unwrap();
+        assert_eq!(lyric.lines[0].begin, 1000);
+        assert!(matches!(
+            cache.get(ProviderKind::Netease, &track),
+            Some(None)
+        ));
+        assert!(cache.get(ProviderKind::Kugou, &track).is_none());
+
+        let entries = cache.entries();
+        assert_eq!(entries.len(), 2);
+        let (negative, _) = entries
+            .into_iter()
+            .find(|(v, _)| v.key == "netease:artist/song title/200")
+            .unwrap();
+        let expired = CacheEntry {
+            fetched_at: now() - cache.negative_ttl,
+            ..negative
+        };
+        assert!(cache.is_expired(&expired));
+        write(
+            cache.path(&expired.key),
+            serde_json::to_string(&expired).unwrap(),
+        )
+        .unwrap();
+        assert!(cache.get(ProviderKind::Netease, &track).is_none());
+
+        assert_eq!(cache.clear(), Ok(1));
+        assert!(cache.entries().is_empty());
+    }
+
+    #[test]
+    fn test_prune() {
+        let cache = cache("prune", 1);
+        for i in 0..5 {
+            let track = TrackInfo {
+                title: i.to_string(),
+                ..Default::default()
+            };
+            cache.put(ProviderKind::Lrclib, &track, None);
+        }
+        assert!(cache.files().iter().map(|(_, size, _)| size).sum::<u64>() <= 1);
+        let track = TrackInfo::default();
+        cache.put(ProviderKind::Lrclib, &track, None);
+        assert!(cache.get(ProviderKind::Lrclib, &track).is_none());
+    }
+}
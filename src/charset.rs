@@ -0,0 +1,93 @@
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use log::{debug, warn};
+
+/// Decodes a lyric file to UTF-8. `forced` is an encoding label such as `gbk` or `shift_jis`;
+/// without it the BOM is checked first, then UTF-16 and UTF-8 validity, then `chardetng`.
+pub fn decode(bytes: &[u8], forced: Option<&str>) -> String {
+    let encoding = forced
+        .and_then(|label| {
+            let encoding = Encoding::for_label(label.trim().as_bytes());
+            if encoding.is_none() {
+                warn!("Unknown lyric encoding: {}", label);
+            }
+            encoding
+        })
+        .unwrap_or_else(|| detect(bytes));
+    debug!("Decoding lyric as {}", encoding.name());
+    // `decode` still lets a BOM override the chosen encoding and strips it.
+    encoding.decode(bytes).0.into_owned()
+}
+
+fn detect(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    if let Some(encoding) = detect_utf16(bytes) {
+        return encoding;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+/// BOM-less UTF-16 text is mostly ASCII here (timestamps), so one byte of each pair is zero.
+fn detect_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(1024) & !1];
+    if sample.is_empty() {
+        return None;
+    }
+    let pairs = sample.len() / 2;
+    let even = sample.iter().step_by(2).filter(|v| **v == 0).count();
+    let odd = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|v| **v == 0)
+        .count();
+    if odd * 2 > pairs && even * 10 < pairs {
+        Some(UTF_16LE)
+    } else if even * 2 > pairs && odd * 10 < pairs {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LRC: &str = "[00:01.00]晴天 周杰伦\n[00:02.00]故事的小黄花\n";
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(LRC.as_bytes(), None), LRC);
+
+        let (gbk, _, _) = encoding_rs::GBK.encode(LRC);
+        assert_eq!(decode(&gbk, None), LRC);
+        assert_eq!(decode(&gbk, Some("gbk")), LRC);
+
+        let (big5, _, _) =
+            encoding_rs::BIG5.encode("[00:01.00]晴天 周杰倫\n[00:02.00]故事的小黃花\n");
+        assert_eq!(
+            decode(&big5, Some("big5")),
+            "[00:01.00]晴天 周杰倫\n[00:02.00]故事的小黃花\n"
+        );
+
+        let (sjis, _, _) = encoding_rs::SHIFT_JIS.encode("[00:01.00]君の名は。\n");
+        assert_eq!(decode(&sjis, Some("shift_jis")), "[00:01.00]君の名は。\n");
+
+        let utf16le = LRC
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<u8>>();
+        assert_eq!(decode(&utf16le, None), LRC);
+        let mut bom = vec![0xfe, 0xff];
+        bom.extend(LRC.encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(decode(&bom, None), LRC);
+    }
+}
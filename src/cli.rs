@@ -0,0 +1,155 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cache::LyricCache;
+use crate::config::Config;
+use crate::export::ExportFormat;
+use crate::lyric::{lrc_rewrite_loss, Lyric, LyricFormat};
+use crate::spotify::fetch_spotify_lyric;
+
+pub fn convert(
+    config: &Config,
+    input: &str,
+    output: &Path,
+    from: Option<LyricFormat>,
+    to: Option<ExportFormat>,
+) -> Result<(), String> {
+    let lyric = read_input(config, input, from)?;
+    let to = to
+        .or_else(|| ExportFormat::from_path(output))
+        .unwrap_or(ExportFormat::Lrc);
+    let content = lyric.export(to)?;
+    if output == Path::new("-") {
+        print!("{}", content);
+        Ok(())
+    } else {
+        std::fs::write(output, content).map_err(|e| e.to_string())
+    }
+}
+
+/// Reports problems in lyric files. With `fix`, rewrites the files that have any, unless
+/// rewriting an LRC file would lose something it holds.
+pub fn lint(
+    config: &Config,
+    files: &[PathBuf],
+    fix: bool,
+    strip_credits: bool,
+) -> Result<(), String> {
+    let strip_credits = strip_credits || config.strip_credits;
+    let mut problems = 0;
+    for path in files {
+        let format = LyricFormat::from_path(path)
+            .ok_or_else(|| format!("Unknown lyric format: {}", path.to_string_lossy()))?;
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+        let content = format.decode(&bytes, config.lyric_encoding.as_deref())?;
+        let (lyric, issues) = Lyric::lint(&content, format, strip_credits)?;
+        for issue in issues.iter() {
+            println!("{}: {}", path.to_string_lossy(), issue);
+        }
+        if issues.is_empty() {
+            continue;
+        }
+        if !fix {
+            problems += issues.len();
+            continue;
+        }
+        let loss = match format {
+            LyricFormat::Lrc if content.as_bytes() != bytes => {
+                Some("it would be re-encoded as UTF-8".to_owned())
+            }
+            LyricFormat::Lrc => lrc_rewrite_loss(&content),
+            _ => None,
+        };
+        if let Some(loss) = loss {
+            println!("{}: not fixed, {}", path.to_string_lossy(), loss);
+            problems += issues.len();
+            continue;
+        }
+        let export = match format {
+            LyricFormat::Lrc => ExportFormat::Elrc,
+            LyricFormat::Ttml => ExportFormat::Ttml,
+            _ => {
+                println!(
+                    "{}: not fixed, can't rewrite this format",
+                    path.to_string_lossy()
+                );
+                problems += issues.len();
+                continue;
+            }
+        };
+        std::fs::write(path, lyric.export(export)?).map_err(|e| e.to_string())?;
+        println!("{}: fixed", path.to_string_lossy());
+    }
+    if problems > 0 {
+        return Err(format!("{} problem(s) found", problems));
+    }
+    Ok(())
+}
+
+/// Prints the cached lyrics, oldest first, or removes them all with `clear`.
+pub fn cache(config: &Config, clear: bool) -> Result<(), String> {
+    let cache = LyricCache::new(config).ok_or_else(|| "The lyric cache is off".to_owned())?;
+    if clear {
+        let count = cache.clear()?;
+        println!("Removed {} cached lyric(s)", count);
+        return Ok(());
+    }
+    let entries = cache.entries();
+    let mut total = 0;
+    for (entry, size) in entries.iter() {
+        let status = match (&entry.lyric, cache.is_expired(entry)) {
+            (_, true) => "expired",
+            (Some(_), false) => "found",
+            (None, false) => "not found",
+        };
+        let age = SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(entry.fetched_at))
+            .map_or(0, |v| v.as_secs());
+        println!(
+            "{:>6}h {:>7} {:<9} {}",
+            age / 3600,
+            format_size(*size),
+            status,
+            entry.key
+        );
+        total += size;
+    }
+    println!(
+        "{} entries, {} in {}",
+        entries.len(),
+        format_size(total),
+        cache.dir().to_string_lossy()
+    );
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1 << 20 {
+        format!("{:.1}K", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1}M", bytes as f64 / (1 << 20) as f64)
+    }
+}
+
+fn read_input(config: &Config, input: &str, from: Option<LyricFormat>) -> Result<Lyric, String> {
+    if let Some(track) = input.strip_prefix("spotify:") {
+        let track = track.rsplit([':', '/']).next().unwrap_or(track);
+        let (Some(token), Some(client_token)) = (
+            config.spotify_access_token.as_ref(),
+            config.spotify_client_token.as_ref(),
+        ) else {
+            return Err("Spotify tokens are not configured".to_owned());
+        };
+        return fetch_spotify_lyric(token, client_token, Some(track.to_owned()))
+            .ok_or_else(|| format!("No Spotify lyric for {}", track));
+    }
+    let path = Path::new(input);
+    let encoding = config.lyric_encoding.as_deref();
+    match from {
+        Some(format) => {
+            let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+            Lyric::parse(&format.decode(&bytes, encoding)?, format)
+        }
+        None => Lyric::from_file(path, encoding),
+    }
+}
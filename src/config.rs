@@ -1,3 +1,4 @@
+use crate::provider::ProviderKind;
 use eframe::egui::ecolor::HexColor;
 use log::info;
 use serde::de::Visitor;
@@ -6,7 +7,7 @@ use std::fs::read_to_string;
 use std::path::PathBuf;
 use std::str::FromStr;
 
-static DEFAULT_CONFIG: &'static str = include_str!("../config.yaml");
+static DEFAULT_CONFIG: &str = include_str!("../config.yaml");
 struct HexColorVisitor;
 
 impl Visitor<'_> for HexColorVisitor {
@@ -20,8 +21,8 @@ impl Visitor<'_> for HexColorVisitor {
     where
         E: serde::de::Error,
     {
-        Ok(HexColor::from_str(v)
-            .map_err(|_| serde::de::Error::custom(format!("Can not identify {} as HexColor", v)))?)
+        HexColor::from_str(v)
+            .map_err(|_| serde::de::Error::custom(format!("Can not identify {} as HexColor", v)))
     }
 }
 
@@ -36,7 +37,87 @@ fn deserialize_hex_color<'de, D>(deserializer: D) -> Result<HexColor, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    Ok(deserializer.deserialize_str(HexColorVisitor)?)
+    deserializer.deserialize_str(HexColorVisitor)
+}
+
+/// Accepts a single string as well as a list, so `lyric_dir: "~/Music"` keeps working.
+fn deserialize_one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(v) => vec![v],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+fn default_lyric_templates() -> Vec<String> {
+    [
+        "{artist}/{album}/{title}.lrc",
+        "{artist} - {title}.lrc",
+        "{stem}.lrc",
+    ]
+    .map(str::to_owned)
+    .to_vec()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Online lyric sites see what is playing, so they are only used once added to `providers`.
+fn default_providers() -> Vec<ProviderKind> {
+    vec![
+        ProviderKind::Feeluown,
+        ProviderKind::Spotify,
+        ProviderKind::Local,
+    ]
+}
+
+fn default_lrclib_url() -> String {
+    "https://lrclib.net".to_owned()
+}
+
+fn default_netease_url() -> String {
+    "http://localhost:3000".to_owned()
+}
+
+fn default_qq_music_url() -> String {
+    "https://c.y.qq.com".to_owned()
+}
+
+fn default_kugou_url() -> String {
+    "https://lyrics.kugou.com".to_owned()
+}
+
+fn default_cache_ttl_hours() -> u64 {
+    24 * 30
+}
+
+fn default_cache_negative_ttl_hours() -> u64 {
+    24
+}
+
+fn default_cache_max_size_mb() -> u64 {
+    50
+}
+
+fn default_gap_threshold() -> u64 {
+    5000
+}
+
+fn default_sung_color() -> HexColor {
+    HexColor::from_str("#2cbebdff").unwrap()
+}
+
+fn default_unsung_color() -> HexColor {
+    HexColor::from_str("#ffffff60").unwrap()
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -45,6 +126,18 @@ pub struct Vec2 {
     pub y: f32,
 }
 
+/// Where lyrics embedded in the audio file's tags rank against lyric files.
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddedLyric {
+    /// Use embedded lyrics before looking for lyric files.
+    Prefer,
+    /// Use embedded lyrics when no lyric file is found.
+    #[default]
+    Fallback,
+    Ignore,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Config {
     #[serde(
@@ -60,7 +153,13 @@ pub struct Config {
     pub text_size: f32,
     pub default_size: Vec2,
     pub passthrough: bool,
-    pub lyric_dir: String,
+    /// Directories searched for lyric files, subdirectories included.
+    #[serde(alias = "lyric_dir", deserialize_with = "deserialize_one_or_many")]
+    pub lyric_dirs: Vec<String>,
+    /// Lyric file paths tried in every lyric directory before searching by the audio file's
+    /// name. `{artist}`, `{artists}`, `{album}`, `{title}` and `{stem}` come from the track.
+    #[serde(default = "default_lyric_templates")]
+    pub lyric_templates: Vec<String>,
     pub font_path: Option<String>,
     pub font_name: Option<String>,
     pub player_name: String,
@@ -68,6 +167,63 @@ pub struct Config {
     pub auto_resize: bool,
     pub spotify_access_token: Option<String>,
     pub spotify_client_token: Option<String>,
+    /// Fill the current line from left to right as it is sung.
+    #[serde(default)]
+    pub karaoke: bool,
+    #[serde(
+        default = "default_sung_color",
+        serialize_with = "serialize_hex_color",
+        deserialize_with = "deserialize_hex_color"
+    )]
+    pub sung_color: HexColor,
+    #[serde(
+        default = "default_unsung_color",
+        serialize_with = "serialize_hex_color",
+        deserialize_with = "deserialize_hex_color"
+    )]
+    pub unsung_color: HexColor,
+    #[serde(default = "default_true")]
+    pub show_translation: bool,
+    /// Preferred `<song>.<language>.lrc` translation sidecar, e.g. `zh`.
+    #[serde(default)]
+    pub translation_language: Option<String>,
+    /// Encoding label (e.g. `gbk`, `big5`, `shift_jis`) forced on local lyric files.
+    /// Detected from the BOM and content when unset.
+    #[serde(default)]
+    pub lyric_encoding: Option<String>,
+    /// Drop credit lines such as `作词 : …` from the lyric.
+    #[serde(default)]
+    pub strip_credits: bool,
+    #[serde(default)]
+    pub embedded_lyric: EmbeddedLyric,
+    /// Shortest break between lines, in milliseconds, shown as an instrumental countdown.
+    /// `0` turns the countdown off.
+    #[serde(default = "default_gap_threshold")]
+    pub gap_threshold: u64,
+    /// Lyric sources, tried in order until one has a lyric for the track.
+    #[serde(default = "default_providers")]
+    pub providers: Vec<ProviderKind>,
+    /// Base URL of the LRCLIB instance used by the `lrclib` provider.
+    #[serde(default = "default_lrclib_url")]
+    pub lrclib_url: String,
+    /// Base URL of the NeteaseCloudMusicApi server used by the `netease` provider.
+    #[serde(default = "default_netease_url")]
+    pub netease_url: String,
+    /// Base URL of the QQ Music endpoints used by the `qqmusic` provider.
+    #[serde(default = "default_qq_music_url")]
+    pub qq_music_url: String,
+    /// Base URL of the Kugou lyric service used by the `kugou` provider.
+    #[serde(default = "default_kugou_url")]
+    pub kugou_url: String,
+    /// How long fetched lyrics stay in the cache. `0` turns the cache off.
+    #[serde(default = "default_cache_ttl_hours")]
+    pub cache_ttl_hours: u64,
+    /// How long a provider having no lyric for a track is remembered. `0` never remembers it.
+    #[serde(default = "default_cache_negative_ttl_hours")]
+    pub cache_negative_ttl_hours: u64,
+    /// Size the cache is trimmed to, oldest entries first.
+    #[serde(default = "default_cache_max_size_mb")]
+    pub cache_max_size_mb: u64,
 }
 
 impl Config {
@@ -76,6 +232,11 @@ impl Config {
         serde_yaml::from_str(&content).unwrap()
     }
 
+    /// `lyric_dirs` with `~` and environment variables expanded.
+    pub fn lyric_dirs(&self) -> Vec<PathBuf> {
+        self.lyric_dirs.iter().map(|v| expand_path(v)).collect()
+    }
+
     pub fn init() -> (Self, PathBuf) {
         if let Some(home_dir) = dirs::home_dir() {
             let path = home_dir
@@ -84,7 +245,7 @@ impl Config {
                 .join("config.yaml");
             (
                 if !path.exists() {
-                    std::fs::create_dir_all(&path.parent().unwrap()).unwrap();
+                    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
                     std::fs::write(&path, DEFAULT_CONFIG.as_bytes()).unwrap();
                     info!("Using default config file");
                     serde_yaml::from_str(DEFAULT_CONFIG).unwrap()
@@ -98,7 +259,7 @@ impl Config {
             let path: PathBuf = "./config.yaml".into();
             (
                 if !path.exists() {
-                    std::fs::create_dir_all(&path.parent().unwrap()).unwrap();
+                    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
                     std::fs::write(&path, DEFAULT_CONFIG.as_bytes()).unwrap();
                     info!("Using default config file");
                     serde_yaml::from_str(DEFAULT_CONFIG).unwrap()
@@ -111,3 +272,72 @@ impl Config {
         }
     }
 }
+
+/// Expands a leading `~` and `$VAR` or `${VAR}` environment variables such as `$HOME`.
+/// Unset variables are left as they are.
+pub fn expand_path(path: &str) -> PathBuf {
+    let path = match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home.to_string_lossy(), rest)
+        }
+        _ => path.to_owned(),
+    };
+    let mut result = String::new();
+    let mut rest = path.as_str();
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let (name, len) = match rest.strip_prefix('{').and_then(|v| v.find('}')) {
+            Some(end) => (&rest[1..end + 1], end + 2),
+            None => {
+                let end = rest
+                    .find(|v: char| !v.is_ascii_alphanumeric() && v != '_')
+                    .unwrap_or(rest.len());
+                (&rest[..end], end)
+            }
+        };
+        match std::env::var(name).ok().filter(|_| !name.is_empty()) {
+            Some(value) => result.push_str(&value),
+            None => {
+                result.push('$');
+                result.push_str(&rest[..len]);
+            }
+        }
+        rest = &rest[len..];
+    }
+    result.push_str(rest);
+    PathBuf::from(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_path() {
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(
+            expand_path("~/Music"),
+            PathBuf::from(format!("{}/Music", home))
+        );
+        assert_eq!(expand_path("$HOME/Music"), expand_path("~/Music"));
+        assert_eq!(
+            expand_path("${HOME}/a$b"),
+            PathBuf::from(format!("{}/a$b", home))
+        );
+        assert_eq!(
+            expand_path("/x/~user/$DESKTOP_LYRIC_UNSET/$"),
+            PathBuf::from("/x/~user/$DESKTOP_LYRIC_UNSET/$")
+        );
+    }
+
+    #[test]
+    fn test_lyric_dirs() {
+        let config: Config = serde_yaml::from_str(DEFAULT_CONFIG).unwrap();
+        assert_eq!(config.lyric_dirs, ["~/Music"]);
+        assert_eq!(config.lyric_templates, default_lyric_templates());
+        let old = DEFAULT_CONFIG.replace("lyric_dirs: [\"~/Music\"]", "lyric_dir: \"/lyrics\"");
+        let config: Config = serde_yaml::from_str(&old).unwrap();
+        assert_eq!(config.lyric_dirs, ["/lyrics"]);
+    }
+}
@@ -0,0 +1,316 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use id3::frame::{SynchronisedLyrics, TimestampFormat};
+use log::{debug, info};
+
+use crate::lyric::{Lyric, LyricLine, LyricWord};
+
+/// FLAC metadata block holding the Vorbis comments.
+const FLAC_VORBIS_COMMENT: u8 = 4;
+/// Largest MP4 `moov` atom read into memory when looking for `©lyr`.
+const MP4_MAX_MOOV: u64 = 64 << 20;
+
+/// Reads lyrics stored in the tags of an audio file: ID3v2 `SYLT`/`USLT` (MP3, WAV, AIFF),
+/// Vorbis comments `LYRICS`/`UNSYNCEDLYRICS` (FLAC, Ogg) and MP4 `©lyr`.
+pub fn read_embedded_lyric(path: &Path) -> Option<Lyric> {
+    let file = File::open(path).ok()?;
+    let lyric = read_lyric(BufReader::new(file));
+    if lyric.is_some() {
+        info!("Using lyric embedded in: {}", path.to_string_lossy());
+    }
+    lyric
+}
+
+/// Picks the tag format from the magic bytes at the start of `reader`.
+fn read_lyric<R: Read + Seek>(mut reader: R) -> Option<Lyric> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic).ok()?;
+    reader.rewind().ok()?;
+    let lyric = match (&magic[..4], &magic[4..]) {
+        (b"fLaC", _) => Lyric::from_text(&read_flac_lyric(reader)?),
+        (b"OggS", _) => Lyric::from_text(&read_ogg_lyric(reader)?),
+        (_, b"ftyp") => Lyric::from_text(&read_mp4_lyric(reader)?),
+        _ => read_id3_lyric(reader)?,
+    };
+    Some(lyric).filter(|v| !v.lines.is_empty())
+}
+
+/// Prefers millisecond-timed `SYLT` frames over plain `USLT` text.
+fn read_id3_lyric<R: Read + Seek>(reader: R) -> Option<Lyric> {
+    let tag = id3::Tag::read_from2(reader)
+        .map_err(|e| debug!("No ID3 tag: {}", e))
+        .ok()?;
+    let lyric = tag
+        .synchronised_lyrics()
+        .find_map(from_sylt)
+        .or_else(|| tag.lyrics().next().map(|v| Lyric::from_text(&v.text)));
+    lyric
+}
+
+/// Each `SYLT` entry is a line, unless entries start with a line break, in which case they
+/// are the timed words of the line they start or continue.
+fn from_sylt(sylt: &SynchronisedLyrics) -> Option<Lyric> {
+    if sylt.timestamp_format != TimestampFormat::Ms {
+        debug!("Skipping SYLT frame timed in MPEG frames");
+        return None;
+    }
+    let is_break = |v: &str| v.starts_with(['\n', '\r']);
+    let word_timing = sylt.content.iter().skip(1).any(|(_, v)| is_break(v));
+    let mut lines: Vec<LyricLine> = Vec::new();
+    for (time, text) in sylt.content.iter() {
+        let time = *time as u64;
+        if !word_timing {
+            lines.push(LyricLine {
+                begin: time,
+                ..LyricLine::new(text.trim())
+            });
+            continue;
+        }
+        if is_break(text) || lines.is_empty() {
+            lines.push(LyricLine {
+                begin: time,
+                ..Default::default()
+            });
+        }
+        let line = lines.last_mut().unwrap();
+        if let Some(prev) = line.words.last_mut() {
+            prev.end = time;
+        }
+        line.words.push(LyricWord {
+            begin: time,
+            end: 0,
+            content: text.trim_start_matches(['\n', '\r']).to_owned(),
+        });
+    }
+    for line in lines.iter_mut().filter(|v| !v.words.is_empty()) {
+        let first = line.words.first_mut().unwrap();
+        first.content = first.content.trim_start().to_owned();
+        let last = line.words.last_mut().unwrap();
+        last.content = last.content.trim_end().to_owned();
+        line.content = line.words.iter().map(|v| v.content.as_str()).collect();
+    }
+    let mut lyric = Lyric {
+        lines,
+        ..Default::default()
+    };
+    lyric.normalize(false);
+    Some(lyric)
+}
+
+fn read_flac_lyric<R: Read + Seek>(mut reader: R) -> Option<String> {
+    reader.seek(SeekFrom::Start(4)).ok()?;
+    loop {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header).ok()?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        if header[0] & 0x7f == FLAC_VORBIS_COMMENT {
+            let mut block = vec![0; len as usize];
+            reader.read_exact(&mut block).ok()?;
+            return vorbis_comment_lyric(&block);
+        }
+        if header[0] & 0x80 != 0 {
+            return None;
+        }
+        reader.seek(SeekFrom::Current(len.into())).ok()?;
+    }
+}
+
+/// The comment header is the second packet of both Vorbis and Opus streams.
+fn read_ogg_lyric<R: Read + Seek>(reader: R) -> Option<String> {
+    let mut packets = ogg::PacketReader::new(reader);
+    packets.read_packet().ok()??;
+    let packet = packets.read_packet().ok()??;
+    let comment = packet
+        .data
+        .strip_prefix(b"\x03vorbis")
+        .or_else(|| packet.data.strip_prefix(b"OpusTags"))?;
+    vorbis_comment_lyric(comment)
+}
+
+/// Finds `LYRICS`, or failing that `UNSYNCEDLYRICS`, in a Vorbis comment block.
+fn vorbis_comment_lyric(block: &[u8]) -> Option<String> {
+    let mut rest = block;
+    let mut take = |len: usize| -> Option<&[u8]> {
+        let (head, tail) = rest.split_at_checked(len)?;
+        rest = tail;
+        Some(head)
+    };
+    let read_u32 = |v: &[u8]| u32::from_le_bytes(v.try_into().unwrap()) as usize;
+    let vendor = read_u32(take(4)?);
+    take(vendor)?;
+    let count = read_u32(take(4)?);
+    let mut unsynced = None;
+    for _ in 0..count {
+        let len = read_u32(take(4)?);
+        let comment = String::from_utf8_lossy(take(len)?);
+        let Some((key, value)) = comment.split_once('=') else {
+            continue;
+        };
+        if key.eq_ignore_ascii_case("LYRICS") {
+            return Some(value.to_owned());
+        }
+        if key.eq_ignore_ascii_case("UNSYNCEDLYRICS") {
+            unsynced = Some(value.to_owned());
+        }
+    }
+    unsynced
+}
+
+/// Walks `moov/udta/meta/ilst/©lyr/data` in an MP4 (M4A) file.
+fn read_mp4_lyric<R: Read + Seek>(mut reader: R) -> Option<String> {
+    let moov = loop {
+        let mut header = [0; 8];
+        reader.read_exact(&mut header).ok()?;
+        let mut size = u32::from_be_bytes(header[..4].try_into().unwrap()) as u64;
+        let mut header_size = 8;
+        if size == 1 {
+            let mut large = [0; 8];
+            reader.read_exact(&mut large).ok()?;
+            size = u64::from_be_bytes(large);
+            header_size = 16;
+        }
+        let is_moov = &header[4..] == b"moov";
+        // Size 0 is the last atom, running to the end of the file.
+        if size == 0 {
+            if !is_moov {
+                return None;
+            }
+            let mut moov = Vec::new();
+            (&mut reader)
+                .take(MP4_MAX_MOOV + 1)
+                .read_to_end(&mut moov)
+                .ok()?;
+            break Some(moov).filter(|v| v.len() as u64 <= MP4_MAX_MOOV)?;
+        }
+        let body = size.checked_sub(header_size)?;
+        if is_moov && body <= MP4_MAX_MOOV {
+            let mut moov = vec![0; body as usize];
+            reader.read_exact(&mut moov).ok()?;
+            break moov;
+        }
+        reader.seek(SeekFrom::Current(body as i64)).ok()?;
+    };
+    let udta = mp4_child(&moov, b"udta")?;
+    let meta = mp4_child(udta, b"meta")?;
+    // `meta` is usually a full box with four bytes of version and flags before its children.
+    let meta = if meta.starts_with(&[0; 4]) {
+        &meta[4..]
+    } else {
+        meta
+    };
+    let lyric = mp4_child(mp4_child(meta, b"ilst")?, b"\xa9lyr")?;
+    let data = mp4_child(lyric, b"data")?;
+    // Skip the type indicator and locale.
+    Some(String::from_utf8_lossy(data.get(8..)?).into_owned())
+}
+
+/// Body of the first child atom of `kind`.
+fn mp4_child<'a>(mut data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        let atom = data.get(..size).filter(|_| size >= 8)?;
+        if &atom[4..8] == kind {
+            return Some(&atom[8..]);
+        }
+        data = &data[size..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use id3::TagLike;
+
+    use super::*;
+
+    fn atom(kind: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut atom = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        atom.extend(kind);
+        atom.extend(body);
+        atom
+    }
+
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let mut block = 4u32.to_le_bytes().to_vec();
+        block.extend(b"test");
+        block.extend((comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            block.extend((comment.len() as u32).to_le_bytes());
+            block.extend(comment.as_bytes());
+        }
+        block
+    }
+
+    #[test]
+    fn test_read_lyric() {
+        let mut tag = id3::Tag::new();
+        tag.add_frame(SynchronisedLyrics {
+            lang: "eng".to_owned(),
+            timestamp_format: TimestampFormat::Ms,
+            content_type: id3::frame::SynchronisedLyricsType::Lyrics,
+            description: String::new(),
+            content: vec![
+                (1000, "Never ".to_owned()),
+                (1500, "gonna".to_owned()),
+                (2000, "\nGive ".to_owned()),
+                (2500, "you up".to_owned()),
+            ],
+        });
+        let mut mp3 = Vec::new();
+        tag.write_to(&mut mp3, id3::Version::Id3v24).unwrap();
+        let lyric = read_lyric(Cursor::new(mp3)).unwrap();
+        assert_eq!(lyric.lines.len(), 2);
+        assert_eq!(lyric.lines[0].content, "Never gonna");
+        assert_eq!((lyric.lines[0].begin, lyric.lines[0].end), (1000, 2000));
+        assert_eq!(lyric.lines[0].words[1].end, 2000);
+        assert_eq!(lyric.lines[1].content, "Give you up");
+
+        let mut tag = id3::Tag::new();
+        tag.add_frame(id3::frame::Lyrics {
+            lang: "eng".to_owned(),
+            description: String::new(),
+            text: "[00:01.00]first\n[00:02.00]second\n".to_owned(),
+        });
+        let mut mp3 = Vec::new();
+        tag.write_to(&mut mp3, id3::Version::Id3v23).unwrap();
+        let lyric = read_lyric(Cursor::new(mp3)).unwrap();
+        assert_eq!(lyric.lines[1].begin, 2000);
+
+        let block = vorbis_comment(&["TITLE=x", "UNSYNCEDLYRICS=plain", "lyrics=[00:01.00]a"]);
+        let mut flac = b"fLaC".to_vec();
+        flac.extend([0, 0, 0, 2, 0, 0]);
+        flac.push(0x80 | FLAC_VORBIS_COMMENT);
+        flac.extend(&(block.len() as u32).to_be_bytes()[1..]);
+        flac.extend(&block);
+        let lyric = read_lyric(Cursor::new(flac)).unwrap();
+        assert_eq!(
+            (lyric.lines[0].begin, lyric.lines[0].content.as_str()),
+            (1000, "a")
+        );
+        assert_eq!(
+            vorbis_comment_lyric(&vorbis_comment(&["UNSYNCEDLYRICS=plain"])).as_deref(),
+            Some("plain")
+        );
+
+        let data = atom(b"data", b"\0\0\0\x01\0\0\0\0first line\nsecond line");
+        let ilst = atom(b"ilst", &atom(b"\xa9lyr", &data));
+        let meta = atom(b"meta", &[&[0; 4], ilst.as_slice()].concat());
+        let mut mp4 = atom(b"ftyp", b"M4A \0\0\0\0");
+        mp4.extend(atom(b"free", &[0; 16]));
+        mp4.extend(atom(b"moov", &atom(b"udta", &meta)));
+        let lyric = read_lyric(Cursor::new(mp4.clone())).unwrap();
+        assert_eq!(lyric.lines.len(), 2);
+        assert_eq!(lyric.lines[1].content, "second line");
+        // A `moov` of size 0 runs to the end of the file.
+        let moov = mp4.len() - meta.len() - 16;
+        mp4[moov..moov + 4].copy_from_slice(&[0; 4]);
+        let lyric = read_lyric(Cursor::new(mp4)).unwrap();
+        assert_eq!(lyric.lines.len(), 2);
+
+        assert!(read_lyric(Cursor::new(b"not audio at all".to_vec())).is_none());
+    }
+}
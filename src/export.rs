@@ -0,0 +1,173 @@
+use std::borrow::Cow;
+use std::path::Path;
+
+use crate::lyric::{Lyric, LyricLine, LAST_LINE_DURATION};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    Lrc,
+    /// Enhanced LRC (A2) with `<mm:ss.xx>` word timestamps.
+    Elrc,
+    Srt,
+    Ttml,
+}
+
+impl ExportFormat {
+    /// Guesses the format from an output file name. `.lrc` means plain LRC.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "lrc" => Some(ExportFormat::Lrc),
+            "srt" => Some(ExportFormat::Srt),
+            "ttml" => Some(ExportFormat::Ttml),
+            _ => None,
+        }
+    }
+}
+
+impl Lyric {
+    pub fn export(&self, format: ExportFormat) -> Result<String, String> {
+        match format {
+            ExportFormat::Lrc => Ok(self.to_lrc()),
+            ExportFormat::Elrc => Ok(self.to_enhanced_lrc()),
+            ExportFormat::Srt => Ok(self.to_srt()),
+            ExportFormat::Ttml => self.to_ttml(),
+        }
+    }
+
+    /// Writes line-timed LRC. Translations follow their line with the same timestamp.
+    pub fn to_lrc(&self) -> String {
+        let mut lyric = self.clone();
+        lyric.lines.iter_mut().for_each(|v| v.words.clear());
+        lyric.to_enhanced_lrc()
+    }
+
+    /// Writes SubRip subtitles, with the translation as a second text line.
+    pub fn to_srt(&self) -> String {
+        let mut result = String::new();
+        let lines = self.lines.iter().filter(|v| !v.content.is_empty());
+        for (i, line) in lines.enumerate() {
+            result.push_str(&format!(
+                "{}\n{} --> {}\n{}\n",
+                i + 1,
+                format_srt_timestamp(line.begin),
+                format_srt_timestamp(self.end_of(line)),
+                line.content
+            ));
+            if let Some(translation) = &line.translation {
+                result.push_str(translation);
+                result.push('\n');
+            }
+            result.push('\n');
+        }
+        result
+    }
+
+    /// `end` of the line, or a guess for the last line whose end is unknown.
+    fn end_of(&self, line: &LyricLine) -> u64 {
+        if line.end > line.begin {
+            return line.end;
+        }
+        self.metadata
+            .length
+            .filter(|v| *v > line.begin)
+            .unwrap_or(line.begin + LAST_LINE_DURATION)
+    }
+
+    /// Writes Apple Music style TTML, keeping word timing and translations.
+    pub fn to_ttml(&self) -> Result<String, String> {
+        let lines = self
+            .lines
+            .iter()
+            .map(|line| {
+                let end = self.end_of(line);
+                let words = if line.words.is_empty() {
+                    vec![amll_lyric::LyricWord {
+                        start_time: line.begin,
+                        end_time: end,
+                        word: Cow::Borrowed(line.content.as_str()),
+                    }]
+                } else {
+                    line.words
+                        .iter()
+                        .map(|v| amll_lyric::LyricWord {
+                            start_time: v.begin,
+                            // A last word without an end lasts as long as its line.
+                            end_time: v.known_end().unwrap_or(end),
+                            word: Cow::Borrowed(v.content.as_str()),
+                        })
+                        .collect()
+                };
+                amll_lyric::LyricLine {
+                    words,
+                    translated_lyric: Cow::Borrowed(line.translation.as_deref().unwrap_or("")),
+                    start_time: line.begin,
+                    end_time: end,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let metadata = [
+            ("musicName", &self.metadata.title),
+            ("artists", &self.metadata.artist),
+            ("album", &self.metadata.album),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| {
+            Some((Cow::Borrowed(key), vec![Cow::Borrowed(value.as_deref()?)]))
+        })
+        .collect();
+        amll_lyric::ttml::stringify_ttml(&amll_lyric::ttml::TTMLLyric { lines, metadata })
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Formats milliseconds as `hh:mm:ss,mmm`.
+fn format_srt_timestamp(time: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        time / 3_600_000,
+        time / 60_000 % 60,
+        time / 1000 % 60,
+        time % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyric::LyricFormat;
+
+    #[test]
+    fn test_export() {
+        let lyric = Lyric::from_str(include_str!("fixtures/enhanced.lrc"));
+        assert_eq!(
+            lyric.export(ExportFormat::Lrc).unwrap(),
+            "[00:12.00]Never gonna give you up\n\
+             [00:12.00]绝不会放弃你\n\
+             [00:15.00]Never gonna let you down\n\
+             [00:18.00]Plain line\n\
+             [00:20.00]I <3 you\n"
+        );
+        assert_eq!(
+            lyric.export(ExportFormat::Srt).unwrap(),
+            "1\n00:00:12,000 --> 00:00:15,000\nNever gonna give you up\n绝不会放弃你\n\n\
+             2\n00:00:15,000 --> 00:00:18,000\nNever gonna let you down\n\n\
+             3\n00:00:18,000 --> 00:00:20,000\nPlain line\n\n\
+             4\n00:00:20,000 --> 00:00:25,000\nI <3 you\n\n"
+        );
+        let ttml = lyric.export(ExportFormat::Ttml).unwrap();
+        let reparsed = Lyric::parse(&ttml, LyricFormat::Ttml).unwrap();
+        assert_eq!(reparsed.lines[0].words, lyric.lines[0].words);
+        assert_eq!(reparsed.lines[0].translation, lyric.lines[0].translation);
+        assert_eq!(reparsed.lines[1].content, lyric.lines[1].content);
+        assert_eq!(reparsed.lines[3].end, 25000);
+        let ttml = Lyric::from_str("[00:01.00]a\n[00:02.00]b\n")
+            .export(ExportFormat::Ttml)
+            .unwrap();
+        let reparsed = Lyric::parse(&ttml, LyricFormat::Ttml).unwrap();
+        assert_eq!(
+            (reparsed.lines[1].begin, reparsed.lines[1].end),
+            (2000, 7000)
+        );
+    }
+}
@@ -20,11 +20,8 @@ fn load_font(config: &Config) -> Option<FontData> {
         }
     }
 
-    if let Some(font) = font_loader::system_fonts::get(&FontPropertyBuilder::new().build()) {
-        return Some(FontData::from_owned(font.0));
-    } else {
-        return None;
-    }
+    font_loader::system_fonts::get(&FontPropertyBuilder::new().build())
+        .map(|font| FontData::from_owned(font.0))
 }
 
 pub fn setup_custom_fonts(ctx: &egui::Context, config: &Config) {
@@ -4,8 +4,11 @@ pub struct FuoClient;
 
 impl FuoClient {
     pub fn status(&mut self) -> String {
-        String::from_utf8_lossy(&Command::new("fuo").arg("status").output().unwrap().stdout)
-            .to_string()
+        Command::new("fuo")
+            .arg("status")
+            .output()
+            .map(|v| String::from_utf8_lossy(&v.stdout).to_string())
+            .unwrap_or_default()
     }
 
     pub fn lyric(&mut self) -> Option<String> {
@@ -16,14 +19,13 @@ impl FuoClient {
             .trim()
             .split('\t')
             .nth(0)?;
-        dbg!(song);
         Some(
             String::from_utf8_lossy(
                 &Command::new("fuo")
                     .arg("show")
                     .arg(format!("{}/lyric", song))
                     .output()
-                    .unwrap()
+                    .ok()?
                     .stdout,
             )
             .to_string(),
@@ -0,0 +1,115 @@
+use std::io::Read;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use flate2::read::ZlibDecoder;
+use log::debug;
+use serde::Deserialize;
+
+/// Header of an encrypted KRC file.
+const MAGIC: &[u8] = b"krc1";
+/// XOR key of the zlib stream that follows the header.
+const KEY: [u8; 16] = [
+    0x40, 0x47, 0x61, 0x77, 0x5e, 0x32, 0x74, 0x47, 0x51, 0x36, 0x31, 0x2d, 0xce, 0xd2, 0x6e, 0x69,
+];
+/// `type` of the translation in the `[language:]` block.
+const TRANSLATION: u8 = 1;
+/// `type` of the romaji in the `[language:]` block.
+const ROMAJI: u8 = 0;
+
+#[derive(Deserialize, Debug)]
+struct Language {
+    #[serde(default)]
+    content: Vec<LanguageContent>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct LanguageContent {
+    r#type: u8,
+    /// One entry per lyric line: the whole translation, or the romaji of each word.
+    #[serde(default)]
+    lyric_content: Vec<Vec<String>>,
+}
+
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Decrypts a Kugou `.krc` file to its plain text.
+pub fn decrypt(bytes: &[u8]) -> Result<String, String> {
+    let data = bytes
+        .strip_prefix(MAGIC)
+        .ok_or_else(|| "Not a KRC file".to_owned())?;
+    let data = data
+        .iter()
+        .zip(KEY.iter().cycle())
+        .map(|(a, b)| a ^ b)
+        .collect::<Vec<u8>>();
+    let mut text = String::new();
+    ZlibDecoder::new(data.as_slice())
+        .read_to_string(&mut text)
+        .map_err(|e| e.to_string())?;
+    Ok(text.trim_start_matches('\u{feff}').to_owned())
+}
+
+/// Line translations from the base64 JSON of a `[language:]` tag. Romaji is used when there
+/// is no translation, so Japanese songs still get a second line.
+pub fn translations(language: &str) -> Vec<String> {
+    let language = BASE64_STANDARD
+        .decode(language.trim())
+        .map_err(|e| e.to_string())
+        .and_then(|v| serde_json::from_slice::<Language>(&v).map_err(|e| e.to_string()));
+    let language = match language {
+        Ok(language) => language,
+        Err(e) => {
+            debug!("Invalid KRC language tag: {}", e);
+            return Vec::new();
+        }
+    };
+    let find = |kind| language.content.iter().find(|v| v.r#type == kind);
+    let Some(content) = find(TRANSLATION).or_else(|| find(ROMAJI)) else {
+        return Vec::new();
+    };
+    content
+        .lyric_content
+        .iter()
+        .map(|v| v.concat().trim().to_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+
+    use super::*;
+
+    fn encrypt(text: &str) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        let data = encoder.finish().unwrap();
+        let mut krc = MAGIC.to_vec();
+        krc.extend(data.iter().zip(KEY.iter().cycle()).map(|(a, b)| a ^ b));
+        krc
+    }
+
+    #[test]
+    fn test_decrypt() {
+        let krc = encrypt("\u{feff}[ti:Song]\n[0,1000]<0,1000,0>a\n");
+        assert!(is_encrypted(&krc));
+        assert_eq!(decrypt(&krc).unwrap(), "[ti:Song]\n[0,1000]<0,1000,0>a\n");
+        assert!(decrypt(b"[ti:Song]").is_err());
+    }
+
+    #[test]
+    fn test_translations() {
+        let json = r#"{"content":[{"language":0,"type":0,"lyricContent":[["a ","i"],["u"]]},
+            {"language":0,"type":1,"lyricContent":[["愛"],[""]]}],"version":1}"#;
+        let language = BASE64_STANDARD.encode(json);
+        assert_eq!(translations(&language), ["愛", ""]);
+        let json = r#"{"content":[{"type":0,"lyricContent":[["a ","i"],["u"]]}]}"#;
+        assert_eq!(translations(&BASE64_STANDARD.encode(json)), ["a i", "u"]);
+        assert!(translations("not base64!").is_empty());
+    }
+}
@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Instant;
+
+use log::{debug, error, info};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use simsearch::SimSearch;
+use walkdir::WalkDir;
+
+use crate::lyric::LyricFormat;
+use crate::provider::TrackInfo;
+
+/// Every lyric file under the lyric directories, subdirectories included. Indexed once in the
+/// background, then kept current by watching the trees. Lookups see whatever is indexed so far.
+pub struct LyricLibrary {
+    dirs: Vec<PathBuf>,
+    index: Arc<RwLock<Index>>,
+    /// Set once the trees are watched. Updates stop when the watcher is dropped.
+    _watcher: Arc<Mutex<Option<RecommendedWatcher>>>,
+}
+
+struct Index {
+    /// Lyric files by lowercase file stem.
+    by_stem: HashMap<String, Vec<PathBuf>>,
+    /// The same files searchable by stem, for `fuzzy`.
+    engine: SimSearch<PathBuf>,
+}
+
+impl LyricLibrary {
+    pub fn open(dirs: &[PathBuf]) -> Self {
+        let index = Arc::new(RwLock::new(Index::new()));
+        let watcher = Arc::new(Mutex::new(None));
+        let (walked, watched, walked_dirs) = (index.clone(), watcher.clone(), dirs.to_owned());
+        // Watching and walking a large library take a while, which must not hold up the caller.
+        thread::spawn(move || {
+            // Watch before walking, so files added meanwhile aren't missed.
+            *watched.lock().unwrap() = watch(&walked_dirs, walked.clone())
+                .map_err(|e| error!("Not watching the lyric directories: {}", e))
+                .ok();
+            let start = Instant::now();
+            for dir in walked_dirs.iter() {
+                index_tree(&walked, dir);
+            }
+            info!(
+                "Indexed {} lyric files in {:?} ({:?})",
+                walked
+                    .read()
+                    .unwrap()
+                    .by_stem
+                    .values()
+                    .map(Vec::len)
+                    .sum::<usize>(),
+                walked_dirs,
+                start.elapsed()
+            );
+        });
+        Self {
+            dirs: dirs.to_owned(),
+            index,
+            _watcher: watcher,
+        }
+    }
+
+    /// Lyric files named after `stem`, case-insensitively. Files under directories named after
+    /// the track's album or artist come first, then [`LyricFormat::ALL`] order decides.
+    pub fn find(&self, stem: &str, track: &TrackInfo) -> Vec<PathBuf> {
+        let index = self.index.read().unwrap();
+        let mut paths = index
+            .by_stem
+            .get(&stem.to_lowercase())
+            .cloned()
+            .unwrap_or_default();
+        let hints = [track.album.as_deref(), track.artist()]
+            .into_iter()
+            .flatten()
+            .filter(|v| !v.is_empty())
+            .map(str::to_lowercase)
+            .collect::<Vec<String>>();
+        let format = |path: &Path| {
+            LyricFormat::ALL
+                .iter()
+                .position(|v| Some(*v) == LyricFormat::from_path(path))
+        };
+        paths.sort_by_key(|path| {
+            let dir = path
+                .parent()
+                .map(|v| v.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let misses = hints.iter().filter(|v| !dir.contains(v.as_str())).count();
+            (misses, format(path))
+        });
+        paths
+    }
+
+    /// The lyric file at `path` relative to one of the lyric directories, compared
+    /// case-insensitively. Earlier directories win.
+    pub fn get(&self, path: &Path) -> Option<PathBuf> {
+        let stem = path.file_stem()?.to_string_lossy().to_lowercase();
+        let path = path.to_string_lossy().to_lowercase();
+        let index = self.index.read().unwrap();
+        let paths = index.by_stem.get(&stem)?;
+        self.dirs.iter().find_map(|dir| {
+            paths
+                .iter()
+                .find(|v| {
+                    v.strip_prefix(dir)
+                        .is_ok_and(|v| v.to_string_lossy().to_lowercase() == path)
+                })
+                .cloned()
+        })
+    }
+
+    /// The lyric file whose stem is most similar to `stem`.
+    pub fn search(&self, stem: &str) -> Option<PathBuf> {
+        let index = self.index.read().unwrap();
+        index.engine.search(stem).into_iter().next()
+    }
+}
+
+impl Index {
+    fn new() -> Self {
+        Self {
+            by_stem: HashMap::new(),
+            engine: SimSearch::new(),
+        }
+    }
+
+    fn add_tree(&mut self, dir: &Path) {
+        let files = WalkDir::new(dir)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|v| v.ok())
+            .filter(|v| v.file_type().is_file());
+        for file in files {
+            self.add(file.path());
+        }
+    }
+
+    fn add(&mut self, path: &Path) {
+        if LyricFormat::from_path(path).is_none() || is_translation_sidecar(path) {
+            return;
+        }
+        let Some(stem) = path.file_stem().map(|v| v.to_string_lossy()) else {
+            return;
+        };
+        let paths = self.by_stem.entry(stem.to_lowercase()).or_default();
+        if !paths.iter().any(|v| v == path) {
+            paths.push(path.to_owned());
+        }
+        self.engine.insert(path.to_owned(), &stem);
+    }
+
+    fn remove(&mut self, path: &Path) {
+        let Some(stem) = path.file_stem().map(|v| v.to_string_lossy().to_lowercase()) else {
+            return;
+        };
+        if let Some(paths) = self.by_stem.get_mut(&stem) {
+            paths.retain(|v| v != path);
+            if paths.is_empty() {
+                self.by_stem.remove(&stem);
+            }
+        }
+        self.engine.delete(&path.to_owned());
+    }
+
+    fn remove_tree(&mut self, dir: &Path) {
+        let paths = self
+            .by_stem
+            .values()
+            .flatten()
+            .filter(|v| v.starts_with(dir))
+            .cloned()
+            .collect::<Vec<PathBuf>>();
+        for path in paths {
+            self.remove(&path);
+        }
+    }
+
+    /// Brings `path` up to date after it was created, changed, moved or removed.
+    fn refresh(&mut self, path: &Path) {
+        if LyricFormat::from_path(path).is_some() {
+            self.remove(path);
+            if path.is_file() {
+                self.add(path);
+            }
+            // Whether `Song.zh.lrc` is a sidecar depends on `Song.lrc`.
+            for sidecar in translation_sidecars(path) {
+                self.remove(&sidecar);
+                self.add(&sidecar);
+            }
+        } else if path.is_dir() {
+            self.remove_tree(path);
+            self.add_tree(path);
+        } else if !path.exists() {
+            // A directory moved away or deleted, or just some other file.
+            self.remove_tree(path);
+        }
+    }
+}
+
+/// Adds the files under `dir` one at a time, so lookups and watch events aren't blocked
+/// for the whole walk.
+fn index_tree(index: &RwLock<Index>, dir: &Path) {
+    let files = WalkDir::new(dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|v| v.ok())
+        .filter(|v| v.file_type().is_file());
+    for file in files {
+        let mut index = index.write().unwrap();
+        // Removed since the walk listed it, and the watcher already saw that.
+        if file.path().is_file() {
+            index.add(file.path());
+        }
+    }
+}
+
+fn watch(dirs: &[PathBuf], index: Arc<RwLock<Index>>) -> notify::Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => return error!("Lyric library watch failed: {}", e),
+        };
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+        debug!("Lyric library changed: {:?}", event);
+        let mut index = index.write().unwrap();
+        for path in event.paths.iter() {
+            index.refresh(path);
+        }
+    })?;
+    for dir in dirs {
+        // A missing directory shouldn't stop the others from being watched.
+        if let Err(e) = watcher.watch(dir, RecursiveMode::Recursive) {
+            error!("Not watching {}: {}", dir.to_string_lossy(), e);
+        }
+    }
+    Ok(watcher)
+}
+
+/// `Song.zh.lrc` is a sidecar when `Song.lrc` exists in the same directory.
+fn is_translation_sidecar(path: &Path) -> bool {
+    let Some(stem) = path.file_stem().map(Path::new) else {
+        return false;
+    };
+    if stem.extension().is_none() {
+        return false;
+    }
+    match stem.file_stem() {
+        Some(original) => path
+            .with_file_name(format!("{}.lrc", original.to_string_lossy()))
+            .exists(),
+        None => false,
+    }
+}
+
+/// The `Song.*.lrc` files next to `Song.lrc`, whether or not it still exists.
+fn translation_sidecars(path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (path.parent(), path.file_stem()) else {
+        return Vec::new();
+    };
+    if path.extension().is_none_or(|v| v != "lrc") {
+        return Vec::new();
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|v| v.ok())
+        .map(|v| v.path())
+        .filter(|v| {
+            let sidecar = v.file_stem().map(Path::new);
+            v.extension().is_some_and(|v| v == "lrc")
+                && sidecar.is_some_and(|v| v.extension().is_some() && v.file_stem() == Some(stem))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, remove_file, rename, write};
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Waits for the watcher to catch up with `condition`.
+    fn eventually(condition: impl Fn() -> bool) -> bool {
+        (0..100).any(|_| {
+            std::thread::sleep(Duration::from_millis(20));
+            condition()
+        })
+    }
+
+    #[test]
+    fn test_library() {
+        let dir =
+            std::env::temp_dir().join(format!("desktop_lyric_library_{}", std::process::id()));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(dir.join("Artist/Album")).unwrap();
+        create_dir_all(dir.join("Other/Live")).unwrap();
+        write(dir.join("Artist/Album/01 Intro.lrc"), "").unwrap();
+        write(dir.join("Artist/Album/01 Intro.zh.lrc"), "").unwrap();
+        write(dir.join("Other/Live/01 Intro.ttml"), "").unwrap();
+        write(dir.join("Artist/Album/cover.jpg"), "").unwrap();
+
+        let library = LyricLibrary::open(&[dir.clone(), dir.join("missing")]);
+        let track = TrackInfo {
+            artists: vec!["Artist".to_owned()],
+            album: Some("Album".to_owned()),
+            ..Default::default()
+        };
+        assert!(eventually(|| library.find("01 intro", &track)
+            == [
+                dir.join("Artist/Album/01 Intro.lrc"),
+                dir.join("Other/Live/01 Intro.ttml")
+            ]));
+        assert!(library.find("cover", &track).is_empty());
+        assert_eq!(
+            library.get(Path::new("other/live/01 INTRO.ttml")),
+            Some(dir.join("Other/Live/01 Intro.ttml"))
+        );
+        assert_eq!(library.get(Path::new("Live/01 Intro.ttml")), None);
+        assert_eq!(
+            library
+                .search("intro")
+                .and_then(|v| v.parent().map(Path::to_owned)),
+            dir.join("Artist/Album").into()
+        );
+
+        create_dir_all(dir.join("New")).unwrap();
+        write(dir.join("New/Song.lrc"), "").unwrap();
+        assert!(eventually(|| library.find("song", &track).len() == 1));
+        rename(dir.join("Other"), dir.join("Moved")).unwrap();
+        assert!(eventually(|| library.find("01 intro", &track)
+            == [
+                dir.join("Artist/Album/01 Intro.lrc"),
+                dir.join("Moved/Live/01 Intro.ttml")
+            ]));
+        remove_dir_all(dir.join("New")).unwrap();
+        assert!(eventually(|| library.find("song", &track).is_empty()));
+
+        let original = dir.join("Artist/Album/01 Intro.lrc");
+        assert!(library.find("01 intro.zh", &track).is_empty());
+        remove_file(&original).unwrap();
+        assert!(eventually(
+            || library.find("01 intro.zh", &track) == [dir.join("Artist/Album/01 Intro.zh.lrc")]
+        ));
+        write(&original, "").unwrap();
+        assert!(eventually(|| library
+            .find("01 intro.zh", &track)
+            .is_empty()));
+        let _ = remove_dir_all(&dir);
+    }
+}
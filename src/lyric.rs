@@ -1,32 +1,1481 @@
-#[derive(Debug)]
+use std::ops::Range;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{charset, krc};
+
+/// Largest timestamp difference, in milliseconds, at which a translation line still matches.
+const TRANSLATION_TOLERANCE: u64 = 50;
+/// How long a line, or its last word, is assumed to last when its end is unknown.
+pub const LAST_LINE_DURATION: u64 = 5000;
+/// Time given to each unsynced line when the track length is unknown.
+const UNSYNCED_LINE_DURATION: u64 = 4000;
+/// Extra characters counted for every unsynced line, so short lines don't flash by.
+const UNSYNCED_LINE_WEIGHT: u64 = 8;
+/// Credit lines only count within this many milliseconds of the first or last line.
+const CREDIT_WINDOW: u64 = 10_000;
+/// Credit lines of unsynced lyrics only count within this many lines of either end.
+const CREDIT_LINES: usize = 5;
+/// Keys of credit lines such as `作词 : 方文山`, compared case-insensitively. Keys that are
+/// common in lyrics themselves, such as `Music` or `曲`, are left out.
+const CREDIT_KEYS: &[&str] = &[
+    "作词",
+    "作詞",
+    "作曲",
+    "编曲",
+    "編曲",
+    "演唱",
+    "歌手",
+    "原唱",
+    "制作人",
+    "製作人",
+    "监制",
+    "制作",
+    "录音",
+    "混音",
+    "母带",
+    "和声",
+    "吉他",
+    "贝斯",
+    "键盘",
+    "弦乐",
+    "出品",
+    "发行",
+    "Lyricist",
+    "Lyrics by",
+    "Composer",
+    "Composed by",
+    "Music by",
+    "Arranger",
+    "Arranged by",
+    "Producer",
+    "Produced by",
+    "Written by",
+    "Mixed by",
+    "Mastered by",
+];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Lyric {
+    pub kind: LyricKind,
+    pub metadata: LyricMetadata,
     pub lines: Vec<LyricLine>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LyricKind {
+    #[default]
+    Synced,
+    /// Plain text without timestamps. See [`Lyric::estimate_timing`].
+    Unsynced,
+}
+
+/// ID tags from the lyric header, e.g. `[ar:Artist]` and `[offset:+200]` in LRC.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LyricMetadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub by: Option<String>,
+    /// Track length in milliseconds.
+    pub length: Option<u64>,
+    /// Milliseconds the lyric is shifted earlier by, already applied to the lines.
+    pub offset: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct LyricLine {
     pub begin: u64,
     pub end: u64,
     pub content: String,
+    /// Timed words (or syllables) of the line, empty when the format only has line timing.
+    pub words: Vec<LyricWord>,
+    pub translation: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LyricWord {
+    pub begin: u64,
+    pub end: u64,
+    pub content: String,
+}
+
+/// A problem found by [`Lyric::normalize`], fixed on the spot.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LintIssue {
+    /// Lines are not in time order.
+    Unsorted,
+    /// A line repeating the one before it at the same time, or a translation repeating its line.
+    Duplicate { begin: u64, content: String },
+    /// An end time before the line begins.
+    InvalidEnd { begin: u64, end: u64 },
+    /// A credit line such as `作词 : 方文山`, only reported when stripping credits.
+    Credit { begin: u64, content: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LyricFormat {
+    Lrc,
+    Ttml,
+    /// NetEase Cloud Music word-level lyrics.
+    Yrc,
+    /// QQ Music word-level lyrics, raw or wrapped in the plaintext `QrcInfos` XML.
+    Qrc,
+    /// Kugou word-level lyrics, usually encrypted.
+    Krc,
+}
+
+impl LyricFormat {
+    pub const ALL: [LyricFormat; 5] = [
+        LyricFormat::Lrc,
+        LyricFormat::Ttml,
+        LyricFormat::Yrc,
+        LyricFormat::Qrc,
+        LyricFormat::Krc,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LyricFormat::Lrc => "lrc",
+            LyricFormat::Ttml => "ttml",
+            LyricFormat::Yrc => "yrc",
+            LyricFormat::Qrc => "qrc",
+            LyricFormat::Krc => "krc",
+        }
+    }
+
+    /// Decodes the bytes of a lyric file to text, decrypting KRC. `encoding` forces a charset label.
+    pub fn decode(&self, bytes: &[u8], encoding: Option<&str>) -> Result<String, String> {
+        match self {
+            LyricFormat::Krc if krc::is_encrypted(bytes) => krc::decrypt(bytes),
+            _ => Ok(charset::decode(bytes, encoding)),
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        Self::ALL.into_iter().find(|v| v.extension() == extension)
+    }
 }
 
 impl Lyric {
+    pub fn parse(content: &str, format: LyricFormat) -> Result<Self, String> {
+        Ok(Self::parse_raw(content, format)?.normalized())
+    }
+
+    /// Parses and normalizes `content`, returning every problem found along the way.
+    pub fn lint(
+        content: &str,
+        format: LyricFormat,
+        strip_credits: bool,
+    ) -> Result<(Self, Vec<LintIssue>), String> {
+        let mut issues = Vec::new();
+        // LRC lines are sorted while parsing, so check the order in the file itself.
+        if format == LyricFormat::Lrc && !lrc_is_sorted(content) {
+            issues.push(LintIssue::Unsorted);
+        }
+        let mut lyric = Self::parse_raw(content, format)?;
+        issues.extend(lyric.normalize(strip_credits));
+        Ok((lyric, issues))
+    }
+
+    fn parse_raw(content: &str, format: LyricFormat) -> Result<Self, String> {
+        match format {
+            LyricFormat::Lrc => Ok(Self::parse_lrc(content)),
+            LyricFormat::Ttml => Self::parse_ttml(content),
+            LyricFormat::Yrc => Ok(Self::parse_yrc(content)),
+            LyricFormat::Qrc => Ok(Self::parse_qrc(content)),
+            LyricFormat::Krc => Ok(Self::parse_krc(content)),
+        }
+    }
+
+    /// Reads a lyric file, choosing the parser by extension. `encoding` forces a charset label.
+    pub fn from_file(path: &Path, encoding: Option<&str>) -> Result<Self, String> {
+        let format = LyricFormat::from_path(path)
+            .ok_or_else(|| format!("Unknown lyric format: {}", path.to_string_lossy()))?;
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+        let content = format.decode(&bytes, encoding)?;
+        match format {
+            // A `.lrc` file without timestamps holds plain lyrics.
+            LyricFormat::Lrc => Ok(Self::from_text(&content)),
+            _ => Self::parse(&content, format),
+        }
+    }
+
     pub fn from_str(lrc: &str) -> Self {
-        let lrc = lrc.replace("&apos;", "'");
-        let mut res = Vec::new();
-        let lrc = amll_lyric::lrc::parse_lrc(lrc.as_str());
-        for i in lrc {
-            res.push(LyricLine {
-                begin: i.start_time,
-                end: i.end_time,
-                content: i
-                    .words
-                    .iter()
-                    .map(|v| v.word.to_string())
-                    .collect::<Vec<String>>()
-                    .join(" "),
+        Self::parse_lrc(lrc).normalized()
+    }
+
+    fn parse_lrc(lrc: &str) -> Self {
+        let mut lyric = Self::from_amll(amll_lyric::lrc::parse_lrc(lrc));
+        lyric.metadata = LyricMetadata::from_tags(lrc);
+        lyric
+            .lines
+            .iter_mut()
+            .for_each(LyricLine::parse_inline_timestamps);
+        lyric.decode_entities();
+        lyric.pair_translations();
+        lyric.apply_offset();
+        lyric
+    }
+
+    /// Text from a source meant to hold plain lyrics, such as an embedded lyric tag. Parsed as
+    /// LRC when it has timed lines, otherwise kept as unsynced lines.
+    pub fn from_text(text: &str) -> Self {
+        let lyric = Self::from_str(text);
+        if !lyric.lines.is_empty() {
+            return lyric;
+        }
+        Self {
+            metadata: lyric.metadata,
+            ..Self::unsynced(text)
+        }
+        .normalized()
+    }
+
+    /// Plain lyric text, one line per line. Header tags such as `[ar:]` are skipped.
+    pub fn unsynced(text: &str) -> Self {
+        Self {
+            kind: LyricKind::Unsynced,
+            metadata: LyricMetadata::default(),
+            lines: text
+                .lines()
+                .map(str::trim)
+                .filter(|v| !v.is_empty() && parse_tag(v).is_none())
+                .map(|v| LyricLine::new(&decode_entities(v)))
+                .collect(),
+        }
+    }
+
+    /// Spreads unsynced lines over `length` milliseconds in proportion to their length.
+    /// Falls back to the `[length:]` tag, then to a fixed duration per line.
+    pub fn estimate_timing(&mut self, length: Option<u64>) {
+        if self.kind != LyricKind::Unsynced || self.lines.is_empty() {
+            return;
+        }
+        let weights = self
+            .lines
+            .iter()
+            .map(|v| v.content.chars().count() as u64 + UNSYNCED_LINE_WEIGHT)
+            .collect::<Vec<u64>>();
+        let total: u64 = weights.iter().sum();
+        let length = length
+            .or(self.metadata.length)
+            .unwrap_or(self.lines.len() as u64 * UNSYNCED_LINE_DURATION);
+        let mut elapsed = 0;
+        for (line, weight) in self.lines.iter_mut().zip(weights) {
+            line.begin = length * elapsed / total;
+            elapsed += weight;
+            line.end = length * elapsed / total;
+        }
+    }
+
+    /// Parses Apple Music style TTML. Background vocals are folded into their main line.
+    fn parse_ttml(ttml: &str) -> Result<Self, String> {
+        let ttml = amll_lyric::ttml::parse_ttml(ttml.as_bytes()).map_err(|e| e.to_string())?;
+        let mut lines: Vec<LyricLine> = Vec::with_capacity(ttml.lines.len());
+        for line in ttml.lines.iter() {
+            let parsed = LyricLine::from_amll(line);
+            match lines.last_mut() {
+                Some(prev) if line.is_bg => prev.append_background(parsed),
+                _ => lines.push(parsed),
+            }
+        }
+        Ok(Self {
+            metadata: LyricMetadata::from_ttml(&ttml.metadata),
+            lines,
+            ..Default::default()
+        })
+    }
+
+    pub fn from_yrc(yrc: &str) -> Self {
+        Self::parse_yrc(yrc).normalized()
+    }
+
+    fn parse_yrc(yrc: &str) -> Self {
+        let mut lyric = Self::from_amll(amll_lyric::yrc::parse_yrc(yrc));
+        lyric.decode_entities();
+        lyric
+    }
+
+    pub fn from_qrc(qrc: &str) -> Self {
+        Self::parse_qrc(qrc).normalized()
+    }
+
+    fn parse_qrc(qrc: &str) -> Self {
+        // The `QrcInfos` attribute is unescaped as a whole, raw QRC line by line.
+        let (content, decoded) = match qrc_lyric_content(qrc) {
+            Some(content) => (content, true),
+            None => (qrc.to_owned(), false),
+        };
+        let mut lyric = Self::from_amll(amll_lyric::qrc::parse_qrc(&content));
+        if !decoded {
+            lyric.decode_entities();
+        }
+        lyric.metadata = LyricMetadata::from_tags(&content);
+        lyric.apply_offset();
+        lyric
+    }
+
+    /// Parses decrypted KRC. Translations come from the base64 `[language:]` tag.
+    pub fn from_krc(krc: &str) -> Self {
+        Self::parse_krc(krc).normalized()
+    }
+
+    fn parse_krc(krc: &str) -> Self {
+        let mut lyric = Self {
+            metadata: LyricMetadata::from_tags(krc),
+            ..Default::default()
+        };
+        let mut translations = Vec::new();
+        for line in krc.lines() {
+            match parse_tag(line) {
+                Some(("language", value)) => translations = krc::translations(value),
+                Some(_) => {}
+                None => lyric.lines.extend(LyricLine::from_krc(line.trim())),
+            }
+        }
+        // The translation has an entry for every line, empty ones included.
+        for (line, translation) in lyric.lines.iter_mut().zip(translations) {
+            line.translation = Some(translation).filter(|v| !v.is_empty());
+        }
+        lyric.decode_entities();
+        lyric.apply_offset();
+        lyric
+    }
+
+    /// Decodes HTML entities left by web sources. Done once, while parsing, so that text such
+    /// as `&amp;lt;` ends up as `&lt;` and not `<`.
+    fn decode_entities(&mut self) {
+        self.lines.iter_mut().for_each(LyricLine::decode_entities);
+    }
+
+    fn from_amll(lines: Vec<amll_lyric::LyricLine>) -> Self {
+        Self {
+            lines: lines.iter().map(LyricLine::from_amll).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// A positive `[offset:]` makes the lyric show up earlier. Unknown (zero) end times stay unknown.
+    fn apply_offset(&mut self) {
+        let offset = self.metadata.offset;
+        if offset == 0 {
+            return;
+        }
+        let shift = |time: &mut u64| *time = time.saturating_add_signed(-offset);
+        for line in self.lines.iter_mut() {
+            shift(&mut line.begin);
+            if line.end != 0 {
+                shift(&mut line.end);
+            }
+            for word in line.words.iter_mut() {
+                shift(&mut word.begin);
+                if word.end != 0 {
+                    shift(&mut word.end);
+                }
+            }
+        }
+    }
+
+    /// Bilingual LRC files repeat each timestamp with the translation on the second line.
+    /// Lines are sorted stably by `begin`, so the first line of a pair is the original. Any
+    /// further lines at the same time, such as romaji, are dropped so they don't hide it.
+    fn pair_translations(&mut self) {
+        let mut lines: Vec<LyricLine> = Vec::with_capacity(self.lines.len());
+        for line in self.lines.drain(..) {
+            match lines.last_mut() {
+                Some(prev) if prev.begin == line.begin => {
+                    if prev.content.is_empty() {
+                        *prev = line;
+                    } else if prev.translation.is_none() && !line.content.is_empty() {
+                        prev.translation = Some(line.content);
+                    }
+                }
+                _ => lines.push(line),
+            }
+        }
+        self.lines = lines;
+    }
+
+    /// Fills in missing translations from a separate lyric, such as a `song.zh.lrc` sidecar,
+    /// matching lines by timestamp.
+    pub fn merge_translation(&mut self, translation: &Lyric) {
+        for line in self.lines.iter_mut().filter(|v| v.translation.is_none()) {
+            let index = translation.lines.partition_point(|v| v.begin < line.begin);
+            let nearest = translation.lines[index.saturating_sub(1)..]
+                .iter()
+                .take(2)
+                .min_by_key(|v| v.begin.abs_diff(line.begin));
+            if let Some(nearest) = nearest {
+                if nearest.begin.abs_diff(line.begin) <= TRANSLATION_TOLERANCE
+                    && !nearest.content.is_empty()
+                {
+                    line.translation = Some(nearest.content.clone());
+                }
+            }
+        }
+    }
+
+    /// Drops credit lines near the start or end of the lyric, where lyric sites put them.
+    fn strip_credits(&mut self, issues: &mut Vec<LintIssue>) {
+        // Measured from the other lines, so stripping again removes nothing more.
+        let credits = self
+            .lines
+            .iter()
+            .map(|v| is_credit(&v.content))
+            .collect::<Vec<bool>>();
+        let others = self
+            .lines
+            .iter()
+            .zip(credits.iter())
+            .filter(|(_, credit)| !**credit)
+            .map(|(v, _)| v.begin)
+            .collect::<Vec<u64>>();
+        let first = others.first().copied().unwrap_or_default();
+        let last = others.last().copied().unwrap_or_default();
+        let synced = self.kind != LyricKind::Unsynced;
+        let mut before = 0;
+        let strip = self
+            .lines
+            .iter()
+            .zip(credits)
+            .map(|(line, credit)| {
+                if !credit {
+                    before += 1;
+                    false
+                } else if synced {
+                    line.begin < first + CREDIT_WINDOW || line.begin + CREDIT_WINDOW > last
+                } else {
+                    before < CREDIT_LINES || others.len() - before < CREDIT_LINES
+                }
+            })
+            .collect::<Vec<bool>>();
+        let mut strip = strip.into_iter();
+        self.lines.retain(|line| {
+            if !strip.next().unwrap_or_default() {
+                return true;
+            }
+            issues.push(LintIssue::Credit {
+                begin: line.begin,
+                content: line.content.clone(),
+            });
+            false
+        });
+    }
+
+    /// Cleans up a freshly parsed lyric: sorts the lines, drops duplicates, replaces invalid
+    /// end times and fills in missing ones. Credit lines are removed, and reported, only when
+    /// `strip_credits` is set. Unsynced lyrics only have their credits handled. Running it
+    /// again changes nothing.
+    pub fn normalize(&mut self, strip_credits: bool) -> Vec<LintIssue> {
+        let mut issues = Vec::new();
+        let synced = self.kind != LyricKind::Unsynced;
+        if synced && !self.lines.is_sorted_by_key(|v| v.begin) {
+            issues.push(LintIssue::Unsorted);
+            self.sort();
+        }
+        if strip_credits {
+            self.strip_credits(&mut issues);
+        }
+        if !synced {
+            return issues;
+        }
+
+        let mut lines: Vec<LyricLine> = Vec::with_capacity(self.lines.len());
+        for mut line in self.lines.drain(..) {
+            if line.translation.as_ref() == Some(&line.content) {
+                issues.push(LintIssue::Duplicate {
+                    begin: line.begin,
+                    content: line.content.clone(),
+                });
+                line.translation = None;
+            }
+            match lines.last_mut() {
+                Some(prev) if prev.begin == line.begin && prev.content == line.content => {
+                    issues.push(LintIssue::Duplicate {
+                        begin: line.begin,
+                        content: line.content,
+                    });
+                    if prev.translation.is_none() {
+                        prev.translation = line.translation;
+                    }
+                }
+                _ => lines.push(line),
+            }
+        }
+        self.lines = lines;
+
+        for line in self.lines.iter_mut() {
+            if line.end != 0 && line.end < line.begin {
+                issues.push(LintIssue::InvalidEnd {
+                    begin: line.begin,
+                    end: line.end,
+                });
+                line.end = 0;
+            }
+        }
+        self.fill_end();
+        issues
+    }
+
+    fn normalized(mut self) -> Self {
+        self.normalize(false);
+        self
+    }
+
+    /// Lines without an end time last until the next line begins.
+    fn fill_end(&mut self) {
+        let begins = self
+            .lines
+            .iter()
+            .skip(1)
+            .map(|v| v.begin)
+            .collect::<Vec<u64>>();
+        for (line, next) in self.lines.iter_mut().zip(begins) {
+            if line.end <= line.begin {
+                line.end = next;
+            }
+        }
+        // A last word without a closing tag ends with its line, or stays unknown (0) with it.
+        for line in self.lines.iter_mut() {
+            let end = line.end;
+            if let Some(last) = line.words.last_mut() {
+                if last.end < last.begin && end > last.begin {
+                    last.end = end;
+                }
+            }
+        }
+    }
+
+    /// Sorts lines by `begin` so they can be searched. The sort is stable, keeping the file
+    /// order of lines that share a timestamp.
+    pub fn sort(&mut self) {
+        self.lines.sort_by_key(|v| v.begin);
+    }
+
+    /// Index of the line active at `pos`, i.e. the last line that began at or before it.
+    /// The lines must be sorted.
+    pub fn line_at(&self, pos: u64) -> Option<usize> {
+        self.lines
+            .partition_point(|v| v.begin <= pos)
+            .checked_sub(1)
+    }
+
+    /// The instrumental break after line `index`, or before the first line when `None`, if it
+    /// lasts at least `threshold` milliseconds. A break starts when a line ends or at an empty
+    /// line, and lasts until the next line begins.
+    pub fn gap_after(&self, index: Option<usize>, threshold: u64) -> Option<Range<u64>> {
+        if self.kind == LyricKind::Unsynced || threshold == 0 {
+            return None;
+        }
+        let next = self.lines.get(index.map_or(0, |v| v + 1))?;
+        let begin = match index.map(|v| &self.lines[v]) {
+            None => 0,
+            Some(line) if line.content.is_empty() => line.begin,
+            Some(line) => line.end,
+        };
+        (next.begin.saturating_sub(begin) >= threshold).then_some(begin..next.begin)
+    }
+
+    /// Writes enhanced LRC (A2): `[mm:ss.xx]<mm:ss.xx>word <mm:ss.xx>word <mm:ss.xx>`.
+    /// Translations follow their line with the same timestamp.
+    pub fn to_enhanced_lrc(&self) -> String {
+        let mut result = self.metadata.to_tags();
+        for line in self.lines.iter() {
+            result.push_str(&format!("[{}]", format_timestamp(line.begin)));
+            if line.words.is_empty() {
+                result.push_str(&line.content);
+            }
+            for (i, word) in line.words.iter().enumerate() {
+                result.push_str(&format!(
+                    "<{}>{}",
+                    format_timestamp(word.begin),
+                    word.content
+                ));
+                let next = line.words.get(i + 1).map(|v| v.begin);
+                if word.known_end().is_some_and(|v| Some(v) != next) {
+                    result.push_str(&format!("<{}>", format_timestamp(word.end)));
+                }
+            }
+            result.push('\n');
+            if let Some(translation) = &line.translation {
+                result.push_str(&format!(
+                    "[{}]{}\n",
+                    format_timestamp(line.begin),
+                    translation
+                ));
+            }
+        }
+        result
+    }
+}
+
+/// Tracks the active line of a sorted [`Lyric`] as playback moves. Steady playback costs a
+/// comparison per lookup; seeks fall back to a binary search.
+#[derive(Debug, Clone, Default)]
+pub struct LyricCursor {
+    index: Option<usize>,
+    /// `begin` of the active line, or 0 before the first line.
+    begin: u64,
+    /// `begin` of the line after the active one.
+    next_change: Option<u64>,
+}
+
+impl LyricCursor {
+    /// Moves to the line active at `pos` and returns its index.
+    pub fn seek(&mut self, lyric: &Lyric, pos: u64) -> Option<usize> {
+        let in_range = |begin: u64, next: Option<u64>| begin <= pos && next.is_none_or(|v| pos < v);
+        if self.index.is_some() || self.next_change.is_some() {
+            if in_range(self.begin, self.next_change) {
+                return self.index;
+            }
+            // Steady playback only ever steps onto the following line.
+            let next = self.index.map_or(0, |v| v + 1);
+            if let Some(line) = lyric.lines.get(next) {
+                let after = lyric.lines.get(next + 1).map(|v| v.begin);
+                if in_range(line.begin, after) {
+                    self.set(lyric, Some(next));
+                    return self.index;
+                }
+            }
+        }
+        self.set(lyric, lyric.line_at(pos));
+        self.index
+    }
+
+    fn set(&mut self, lyric: &Lyric, index: Option<usize>) {
+        self.index = index;
+        self.begin = index.map_or(0, |v| lyric.lines[v].begin);
+        self.next_change = lyric.lines.get(index.map_or(0, |v| v + 1)).map(|v| v.begin);
+    }
+
+    /// When the active line changes next, if there is a later line.
+    pub fn next_change(&self) -> Option<u64> {
+        self.next_change
+    }
+}
+
+impl LyricMetadata {
+    /// Reads `[tag:value]` header lines. Timestamp lines are skipped since their tag is numeric.
+    pub fn from_tags(lrc: &str) -> Self {
+        let mut metadata = Self::default();
+        for line in lrc.lines() {
+            let Some((key, value)) = parse_tag(line) else {
+                continue;
+            };
+            let text = Some(decode_entities(value)).filter(|v| !v.is_empty());
+            match key.to_lowercase().as_str() {
+                "ti" => metadata.title = text,
+                "ar" => metadata.artist = text,
+                "al" => metadata.album = text,
+                "by" => metadata.by = text,
+                "length" => metadata.length = parse_length(value),
+                "offset" => metadata.offset = value.parse().unwrap_or(0),
+                _ => {}
+            }
+        }
+        metadata
+    }
+
+    fn from_ttml(metadata: &[(std::borrow::Cow<str>, Vec<std::borrow::Cow<str>>)]) -> Self {
+        let mut result = Self::default();
+        for (key, values) in metadata {
+            let value = Some(values.join(", ")).filter(|v| !v.is_empty());
+            match key.as_ref() {
+                "musicName" => result.title = value,
+                "artists" => result.artist = value,
+                "album" => result.album = value,
+                "ttmlAuthorGithubLogin" => result.by = value,
+                _ => {}
+            }
+        }
+        result
+    }
+
+    fn to_tags(&self) -> String {
+        let mut result = String::new();
+        let tags = [
+            ("ti", &self.title),
+            ("ar", &self.artist),
+            ("al", &self.album),
+            ("by", &self.by),
+        ];
+        for (key, value) in tags {
+            if let Some(value) = value {
+                result.push_str(&format!("[{}:{}]\n", key, value));
+            }
+        }
+        if let Some(length) = self.length {
+            result.push_str(&format!(
+                "[length:{:02}:{:02}]\n",
+                length / 60_000,
+                length / 1000 % 60
+            ));
+        }
+        result
+    }
+
+    /// Whether the tags are consistent with the playing track. Missing tags always match.
+    pub fn matches(&self, title: Option<&str>, artist: Option<&str>) -> bool {
+        fn similar(a: &str, b: &str) -> bool {
+            let normalize = |v: &str| {
+                v.chars()
+                    .filter(|v| v.is_alphanumeric())
+                    .flat_map(char::to_lowercase)
+                    .collect::<String>()
+            };
+            let (a, b) = (normalize(a), normalize(b));
+            a.is_empty() || b.is_empty() || a.contains(&b) || b.contains(&a)
+        }
+        let title_matches = match (&self.title, title) {
+            (Some(a), Some(b)) => similar(a, b),
+            _ => true,
+        };
+        let artist_matches = match (&self.artist, artist) {
+            (Some(a), Some(b)) => similar(a, b),
+            _ => true,
+        };
+        title_matches && artist_matches
+    }
+}
+
+impl LyricLine {
+    pub fn new(content: &str) -> Self {
+        Self {
+            content: content.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn from_amll(line: &amll_lyric::LyricLine) -> Self {
+        let mut words = line
+            .words
+            .iter()
+            .map(|v| LyricWord {
+                begin: v.start_time,
+                end: v.end_time,
+                content: v.word.to_string(),
             })
+            .collect::<Vec<LyricWord>>();
+        // Untimed words (usually the spaces between spans) are sung with the word before them.
+        let mut prev_end = line.start_time;
+        for word in words.iter_mut() {
+            if word.end == 0 {
+                word.begin = prev_end;
+                word.end = prev_end;
+            }
+            prev_end = word.end;
+        }
+        if let Some(first) = words.first_mut() {
+            first.content = first.content.trim_start().to_owned();
+        }
+        if let Some(last) = words.last_mut() {
+            last.content = last.content.trim_end().to_owned();
+        }
+        // A single untimed word is plain line timing, not karaoke.
+        if words.len() == 1 && words[0].end <= words[0].begin {
+            words.clear();
+        }
+        let content = if words.is_empty() {
+            line.words
+                .iter()
+                .map(|v| v.word.as_ref())
+                .collect::<String>()
+                .trim()
+                .to_owned()
+        } else {
+            words.iter().map(|v| v.content.as_str()).collect()
+        };
+        Self {
+            begin: line.start_time,
+            end: line.end_time,
+            content,
+            words,
+            translation: Some(line.translated_lyric.trim())
+                .filter(|v| !v.is_empty())
+                .map(str::to_owned),
+        }
+    }
+
+    /// Parses a `[begin,duration]<offset,duration,0>word...` line, word offsets being
+    /// relative to the line.
+    fn from_krc(line: &str) -> Option<Self> {
+        let (timing, rest) = line.strip_prefix('[')?.split_once(']')?;
+        let (begin, duration) = timing.split_once(',')?;
+        let begin: u64 = begin.trim().parse().ok()?;
+        let end = begin + duration.trim().parse::<u64>().ok()?;
+        let mut words = Vec::new();
+        for word in rest.split('<').skip(1) {
+            let Some((timing, content)) = word.split_once('>') else {
+                continue;
+            };
+            let mut timing = timing.split(',').map(|v| v.trim().parse::<u64>());
+            let (Some(Ok(offset)), Some(Ok(duration))) = (timing.next(), timing.next()) else {
+                continue;
+            };
+            words.push(LyricWord {
+                begin: begin + offset,
+                end: begin + offset + duration,
+                content: content.to_owned(),
+            });
+        }
+        if words.is_empty() {
+            return Some(Self {
+                begin,
+                end,
+                ..Self::new(rest.trim())
+            });
+        }
+        let first = words.first_mut().unwrap();
+        first.content = first.content.trim_start().to_owned();
+        let last = words.last_mut().unwrap();
+        last.content = last.content.trim_end().to_owned();
+        Some(Self {
+            begin,
+            end,
+            content: words.iter().map(|v| v.content.as_str()).collect(),
+            words,
+            translation: None,
+        })
+    }
+
+    fn append_background(&mut self, background: LyricLine) {
+        let text = background
+            .content
+            .trim_start_matches('(')
+            .trim_end_matches(')');
+        if text.is_empty() {
+            return;
+        }
+        self.end = self.end.max(background.end);
+        if self.words.is_empty() || background.words.is_empty() {
+            self.content = format!("{} ({})", self.content, text);
+            return;
+        }
+        let mut words = background.words;
+        if let Some(first) = words.first_mut() {
+            first.content = format!(" ({}", first.content.trim_start_matches('('));
+        }
+        if let Some(last) = words.last_mut() {
+            last.content = format!("{})", last.content.trim_end_matches(')'));
+        }
+        self.words.extend(words);
+        self.content = self.words.iter().map(|v| v.content.as_str()).collect();
+    }
+
+    /// Splits enhanced LRC (A2) `<mm:ss.xx>` inline tags into timed words.
+    /// Each word ends where the next tag starts; a trailing tag closes the last word.
+    fn parse_inline_timestamps(&mut self) {
+        if !self.content.contains('<') {
+            return;
+        }
+        let mut words: Vec<LyricWord> = Vec::new();
+        let mut text = String::new();
+        let mut begin = self.begin;
+        let mut rest = self.content.as_str();
+        loop {
+            let Some(start) = rest.find('<') else {
+                text.push_str(rest);
+                break;
+            };
+            let tag = rest[start..].find('>').and_then(|len| {
+                let time = parse_inline_timestamp(&rest[start + 1..start + len])?;
+                Some((start + len + 1, time))
+            });
+            let Some((end, time)) = tag else {
+                // Not a timestamp, such as the `<` of `I <3 you`.
+                text.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+                continue;
+            };
+            text.push_str(&rest[..start]);
+            if !text.trim().is_empty() {
+                words.push(LyricWord {
+                    begin,
+                    end: time,
+                    content: std::mem::take(&mut text),
+                });
+            } else if let Some(prev) = words.last_mut() {
+                prev.content.push_str(&std::mem::take(&mut text));
+            } else {
+                text.clear();
+            }
+            begin = time;
+            rest = &rest[end..];
+        }
+        if words.is_empty() {
+            return;
+        }
+        if !text.trim().is_empty() {
+            words.push(LyricWord {
+                begin,
+                end: 0,
+                content: text,
+            });
+        }
+        if let Some(first) = words.first_mut() {
+            first.content = first.content.trim_start().to_owned();
+        }
+        if let Some(last) = words.last_mut() {
+            last.content = last.content.trim_end().to_owned();
+        }
+        self.content = words.iter().map(|v| v.content.as_str()).collect();
+        self.words = words;
+    }
+
+    /// Decodes named and numeric HTML entities, such as `&quot;` and `&#39;`, left by web sources.
+    fn decode_entities(&mut self) {
+        self.content = decode_entities(&self.content);
+        for word in self.words.iter_mut() {
+            word.content = decode_entities(&word.content);
+        }
+        if let Some(translation) = self.translation.as_mut() {
+            *translation = decode_entities(translation);
+        }
+    }
+
+    /// Fraction of the line that has been played at `pos`, in `0.0..=1.0`.
+    pub fn progress(&self, pos: u64) -> f32 {
+        progress(self.begin, self.end_or_default(), pos)
+    }
+
+    /// `end`, or [`LAST_LINE_DURATION`] after `begin` when it is unknown.
+    pub fn end_or_default(&self) -> u64 {
+        if self.end > self.begin {
+            self.end
+        } else {
+            self.begin + LAST_LINE_DURATION
+        }
+    }
+
+    /// Number of characters of `content` sung at `pos`, fractional inside the current word.
+    /// Lines without word timing are interpolated over `begin..end`.
+    pub fn sung_chars(&self, pos: u64) -> f32 {
+        if self.words.is_empty() {
+            return self.content.chars().count() as f32 * self.progress(pos);
+        }
+        let line_end = self.end_or_default();
+        self.words
+            .iter()
+            .map(|v| {
+                let end = v.known_end().unwrap_or(line_end);
+                v.content.chars().count() as f32 * progress(v.begin, end, pos)
+            })
+            .sum()
+    }
+}
+
+impl LyricWord {
+    /// `end`, unless the source didn't give one.
+    pub fn known_end(&self) -> Option<u64> {
+        Some(self.end).filter(|v| *v != 0 && *v >= self.begin)
+    }
+}
+
+impl std::fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LintIssue::Unsorted => write!(f, "lines are not in time order"),
+            LintIssue::Duplicate { begin, content } => {
+                write!(
+                    f,
+                    "[{}] duplicate line: {}",
+                    format_timestamp(*begin),
+                    content
+                )
+            }
+            LintIssue::InvalidEnd { begin, end } => write!(
+                f,
+                "[{}] line ends before it begins, at {}",
+                format_timestamp(*begin),
+                format_timestamp(*end)
+            ),
+            LintIssue::Credit { begin, content } => {
+                write!(f, "[{}] credit line: {}", format_timestamp(*begin), content)
+            }
+        }
+    }
+}
+
+/// Whether the first timestamp of every LRC line is at or after the one of the line before.
+/// Lines with several timestamps (`[00:10.00][01:20.00]chorus`) are only checked by the first.
+fn lrc_is_sorted(lrc: &str) -> bool {
+    lrc.lines()
+        .filter_map(|v| amll_lyric::lrc::parse_time(v.trim_start()).ok())
+        .map(|(_, time)| time)
+        .is_sorted()
+}
+
+/// Matches lines starting with `key:` or `key：` whose key, or every `/`-separated part of it,
+/// is a credit.
+fn is_credit(content: &str) -> bool {
+    let Some((key, value)) = content.split_once([':', '：']) else {
+        return false;
+    };
+    !value.trim().is_empty()
+        && key.split('/').all(|key| {
+            CREDIT_KEYS
+                .iter()
+                .any(|v| key.trim().eq_ignore_ascii_case(v))
+        })
+}
+
+/// Unwraps and unescapes the `LyricContent` attribute of a `QrcInfos` document. `None` for
+/// raw QRC.
+fn qrc_lyric_content(qrc: &str) -> Option<String> {
+    const ATTRIBUTE: &str = "LyricContent=\"";
+    let start = qrc.find(ATTRIBUTE)? + ATTRIBUTE.len();
+    let len = qrc[start..].find('"')?;
+    Some(decode_entities(&qrc[start..start + len]))
+}
+
+/// Decodes every named (HTML5) and numeric character reference in `text`.
+pub fn decode_entities(text: &str) -> String {
+    html_escape::decode_html_entities(text).into_owned()
+}
+
+/// What rewriting `lrc` as enhanced LRC would lose, if anything: tags other than the ones
+/// [`LyricMetadata`] keeps, `[offset:]`, lines sharing several timestamps and untimed lines.
+pub fn lrc_rewrite_loss(lrc: &str) -> Option<String> {
+    for line in lrc.lines().map(str::trim).filter(|v| !v.is_empty()) {
+        if let Some((key, _)) = parse_tag(line) {
+            match key.to_lowercase().as_str() {
+                "ti" | "ar" | "al" | "by" | "length" => {}
+                "offset" => return Some("[offset:] would be applied to the timestamps".to_owned()),
+                key => return Some(format!("the [{}:] tag would be dropped", key)),
+            }
+            continue;
+        }
+        let mut rest = line;
+        let mut timestamps = 0;
+        while let Some((tag, after)) = rest.strip_prefix('[').and_then(|v| v.split_once(']')) {
+            if parse_inline_timestamp(tag).is_none() {
+                break;
+            }
+            timestamps += 1;
+            rest = after;
         }
-        Self { lines: res }
+        match timestamps {
+            0 => return Some("lines without a timestamp would be dropped".to_owned()),
+            1 => {}
+            _ => return Some("lines with several timestamps would be split".to_owned()),
+        }
+    }
+    None
+}
+
+/// Splits a `[key:value]` header line. Timestamps such as `[00:01.00]` are not tags.
+fn parse_tag(line: &str) -> Option<(&str, &str)> {
+    let tag = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let (key, value) = tag.split_once(':')?;
+    let key = key.trim();
+    if key.is_empty() || !key.chars().all(|v| v.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some((key, value.trim()))
+}
+
+/// Parses `[length:]` values such as `03:45` or `03:45.20` into milliseconds.
+fn parse_length(value: &str) -> Option<u64> {
+    let (min, sec) = value.split_once(':')?;
+    let sec: f64 = sec.trim().parse().ok()?;
+    Some(min.trim().parse::<u64>().ok()? * 60_000 + (sec * 1000.0) as u64)
+}
+
+fn parse_inline_timestamp(tag: &str) -> Option<u64> {
+    match amll_lyric::lrc::parse_time(&format!("[{}]", tag)) {
+        Ok(("", time)) => Some(time),
+        _ => None,
+    }
+}
+
+/// Formats milliseconds as `mm:ss.xx`, keeping a third fraction digit only when needed.
+fn format_timestamp(time: u64) -> String {
+    let (min, sec, ms) = (time / 60_000, time / 1000 % 60, time % 1000);
+    if ms % 10 == 0 {
+        format!("{:02}:{:02}.{:02}", min, sec, ms / 10)
+    } else {
+        format!("{:02}:{:02}.{:03}", min, sec, ms)
+    }
+}
+
+/// Fraction of `begin..end` played at `pos`, in `0.0..=1.0`.
+pub fn progress(begin: u64, end: u64, pos: u64) -> f32 {
+    if pos <= begin {
+        0.0
+    } else if pos >= end {
+        1.0
+    } else {
+        (pos - begin) as f32 / (end - begin) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_from_str() {
+        let lyric = Lyric::from_str("[00:01.00] first line\n[00:03.50]second line\n");
+        assert_eq!(lyric.lines.len(), 2);
+        assert_eq!(lyric.lines[0].content, "first line");
+        assert_eq!(lyric.lines[0].begin, 1000);
+        assert_eq!(lyric.lines[0].end, 3500);
+        assert_eq!(lyric.lines[0].progress(2250), 0.5);
+    }
+
+    #[test]
+    fn test_unsynced() {
+        let text = "[ti:Plain]\nFirst line\n\nSecond, longer line\n";
+        assert!(Lyric::from_str(text).lines.is_empty());
+        assert!(Lyric::from_str("<html>Not found</html>").lines.is_empty());
+        let mut lyric = Lyric::from_text(text);
+        assert_eq!(lyric.kind, LyricKind::Unsynced);
+        assert_eq!(lyric.metadata.title.as_deref(), Some("Plain"));
+        assert_eq!(lyric.lines.len(), 2);
+        lyric.estimate_timing(Some(35_000));
+        assert_eq!((lyric.lines[0].begin, lyric.lines[0].end), (0, 14_000));
+        assert_eq!((lyric.lines[1].begin, lyric.lines[1].end), (14_000, 35_000));
+
+        let mut lyric = Lyric::unsynced("a\nb\n");
+        lyric.estimate_timing(None);
+        assert_eq!(lyric.lines[1].end, 2 * UNSYNCED_LINE_DURATION);
+        assert_eq!(Lyric::from_text("").lines.len(), 0);
+        assert_eq!(Lyric::from_text("[00:01.00]a\n").kind, LyricKind::Synced);
+        let path = std::env::temp_dir().join(format!("desktop_lyric_{}.lrc", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let lyric = Lyric::from_file(&path, None).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!((lyric.kind, lyric.lines.len()), (LyricKind::Unsynced, 2));
+    }
+
+    #[test]
+    fn test_cursor() {
+        let mut lyric = Lyric::from_str("[00:03.00]c\n[00:01.00]a\n[00:02.00]b\n");
+        lyric.sort();
+        let mut cursor = LyricCursor::default();
+        assert_eq!(cursor.seek(&lyric, 500), None);
+        assert_eq!(cursor.next_change(), Some(1000));
+        assert_eq!(cursor.seek(&lyric, 1000), Some(0));
+        assert_eq!(cursor.seek(&lyric, 1999), Some(0));
+        assert_eq!(cursor.next_change(), Some(2000));
+        assert_eq!(cursor.seek(&lyric, 2500), Some(1));
+        assert_eq!(cursor.seek(&lyric, 9000), Some(2));
+        assert_eq!(cursor.next_change(), None);
+        // Seeking backwards
+        assert_eq!(cursor.seek(&lyric, 1500), Some(0));
+        assert_eq!(cursor.seek(&lyric, 0), None);
+        assert_eq!(lyric.line_at(2000), Some(1));
+    }
+
+    #[test]
+    fn test_gap_after() {
+        let lyric = Lyric::from_str("[00:06.00]a\n[00:08.00]\n[00:20.00]b\n[00:21.00]c\n");
+        assert_eq!(lyric.gap_after(None, 5000), Some(0..6000));
+        assert_eq!(lyric.gap_after(Some(0), 5000), None);
+        assert_eq!(lyric.gap_after(Some(1), 5000), Some(8000..20_000));
+        assert_eq!(lyric.gap_after(Some(1), 0), None);
+        assert_eq!(lyric.gap_after(Some(3), 5000), None);
+        let mut lyric = Lyric::from_yrc("[1000,1000](1000,1000,0)a\n[9000,1000](9000,1000,0)b\n");
+        assert_eq!(lyric.gap_after(Some(0), 5000), Some(2000..9000));
+        lyric.kind = LyricKind::Unsynced;
+        assert_eq!(lyric.gap_after(None, 0), None);
+    }
+
+    #[test]
+    fn test_translation() {
+        let lyric = Lyric::from_str(
+            "[00:01.00]君の名は\n[00:01.00]Your name\n[00:02.00]\n[00:03.00]次の行\n",
+        );
+        assert_eq!(lyric.lines.len(), 3);
+        assert_eq!(lyric.lines[0].content, "君の名は");
+        assert_eq!(lyric.lines[0].translation.as_deref(), Some("Your name"));
+        assert_eq!(lyric.lines[0].end, 2000);
+        assert_eq!(lyric.lines[2].translation, None);
+
+        let lyric = Lyric::from_str(
+            "[00:01.00]君の名は\n[00:01.00]Your name\n[00:01.00]kimi no na wa\n[00:02.00]次\n",
+        );
+        assert_eq!(lyric.lines.len(), 2);
+        assert_eq!(lyric.line_at(1500), Some(0));
+        assert_eq!(lyric.lines[0].content, "君の名は");
+        assert_eq!(lyric.lines[0].translation.as_deref(), Some("Your name"));
+    }
+
+    #[test]
+    fn test_normalize() {
+        let lrc = "[00:03.00]c\n[00:01.00]作词 : 某人\n[00:02.00]b\n[00:02.00]b\n[00:04.00]d\n";
+        let (lyric, issues) = Lyric::lint(lrc, LyricFormat::Lrc, false).unwrap();
+        let duplicate = LintIssue::Duplicate {
+            begin: 2000,
+            content: "b".to_owned(),
+        };
+        assert_eq!(issues, vec![LintIssue::Unsorted, duplicate.clone()]);
+        assert_eq!(lyric.lines.len(), 4);
+        assert_eq!(lyric.lines[1].translation, None);
+        let (lyric, issues) = Lyric::lint(lrc, LyricFormat::Lrc, true).unwrap();
+        assert_eq!(
+            issues,
+            vec![
+                LintIssue::Unsorted,
+                LintIssue::Credit {
+                    begin: 1000,
+                    content: "作词 : 某人".to_owned()
+                },
+                duplicate,
+            ]
+        );
+        let contents = lyric.lines.iter().map(|v| v.content.as_str());
+        assert_eq!(contents.collect::<Vec<_>>(), ["b", "c", "d"]);
+        assert_eq!(issues[0].to_string(), "lines are not in time order");
+        assert_eq!(issues[2].to_string(), "[00:02.00] duplicate line: b");
+
+        let mut lyric = Lyric {
+            lines: vec![
+                LyricLine {
+                    begin: 2000,
+                    end: 1000,
+                    ..LyricLine::new("b")
+                },
+                LyricLine {
+                    begin: 1000,
+                    end: 2000,
+                    ..LyricLine::new("a")
+                },
+                LyricLine {
+                    begin: 3000,
+                    ..LyricLine::new("c")
+                },
+            ],
+            ..Default::default()
+        };
+        let issues = lyric.normalize(false);
+        assert_eq!(issues[0], LintIssue::Unsorted);
+        assert_eq!(
+            issues[1],
+            LintIssue::InvalidEnd {
+                begin: 2000,
+                end: 1000
+            }
+        );
+        assert_eq!((lyric.lines[1].begin, lyric.lines[1].end), (2000, 3000));
+        assert!(lyric.normalize(false).is_empty());
+        assert!(!is_credit("Love: is all you need"));
+        assert_eq!(
+            lrc_rewrite_loss("[ti:Song]\n\n[00:01.00]<00:01.00>a\n"),
+            None
+        );
+        assert!(lrc_rewrite_loss("[re:Editor]\n[00:01.00]a\n").is_some());
+        assert!(lrc_rewrite_loss("[offset:200]\n[00:01.00]a\n").is_some());
+        assert!(lrc_rewrite_loss("[00:01.00][00:05.00]a\n").is_some());
+        assert!(lrc_rewrite_loss("[00:01.00]a\nnote\n").is_some());
+        assert!(!is_credit("Music: the only thing I know"));
+        assert!(is_credit("作词/作曲：周杰伦"));
+        let lrc = "[00:00.00]作词：某人\n[00:05.00]a\n[00:30.00]混音：混音师\n[01:00.00]b\n";
+        let (lyric, issues) = Lyric::lint(lrc, LyricFormat::Lrc, true).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(lyric.lines[0].content, "a");
+        assert_eq!(lyric.lines[1].content, "混音：混音师");
+        let lrc = "[00:00.00]作词：某人\n[00:06.00]作曲：某人\n[00:12.00]编曲：某人\n[00:15.00]a\n";
+        let (mut lyric, _) = Lyric::lint(lrc, LyricFormat::Lrc, true).unwrap();
+        assert_eq!(lyric.lines.len(), 1);
+        assert!(lyric.normalize(true).is_empty());
+    }
+
+    #[test]
+    fn test_entities() {
+        let lyric = Lyric::from_str(
+            "[ar:Simon &amp; Garfunkel]\n[00:01.00]I&apos;m &quot;here&quot; &#39;now&#x27; &lt;3&nbsp;&hellip;\n\
+             [00:02.00]<00:02.00>Rock &amp; <00:02.50>roll\n",
+        );
+        assert_eq!(lyric.metadata.artist.as_deref(), Some("Simon & Garfunkel"));
+        assert_eq!(lyric.lines[0].content, "I'm \"here\" 'now' <3\u{a0}…");
+        assert_eq!(lyric.lines[1].content, "Rock & roll");
+        assert_eq!(lyric.lines[1].words[0].content, "Rock & ");
+        assert_eq!(
+            decode_entities("&amp;lt; &bogus; & &#128512;"),
+            "&lt; &bogus; & 😀"
+        );
+    }
+
+    #[test]
+    fn test_normalize_idempotent() {
+        let mut lyric = Lyric::from_str(
+            "[00:02.00]a &amp;lt; b\n[00:01.00]<00:01.00>Rock &amp;amp; <00:01.50>roll\n\
+             [00:01.00]<00:01.00>Rock &amp;amp; <00:01.50>roll\n[00:03.00]作词：某人\n",
+        );
+        assert_eq!(lyric.lines[1].content, "a &lt; b");
+        let once = lyric.lines.clone();
+        assert!(lyric.normalize(false).is_empty());
+        assert_eq!(lyric.lines, once);
+        let mut qrc = Lyric::from_qrc("[1000,500]a &amp;lt; b(1000,500)");
+        let once = qrc.lines.clone();
+        qrc.normalize(false);
+        assert_eq!(qrc.lines, once);
+        assert_eq!(qrc.lines[0].content, "a &lt; b");
+    }
+
+    #[test]
+    fn test_merge_translation() {
+        let mut lyric = Lyric::from_str("[00:01.00]original\n[00:02.00]second\n[00:05.00]third\n");
+        let translation = Lyric::from_str("[00:01.01]翻译\n[00:02.00]第二\n[00:04.00]无关\n");
+        lyric.merge_translation(&translation);
+        assert_eq!(lyric.lines[0].translation.as_deref(), Some("翻译"));
+        assert_eq!(lyric.lines[1].translation.as_deref(), Some("第二"));
+        assert_eq!(lyric.lines[2].translation, None);
+    }
+
+    #[test]
+    fn test_ttml() {
+        let lyric = Lyric::parse(include_str!("fixtures/sample.ttml"), LyricFormat::Ttml).unwrap();
+        assert_eq!(lyric.lines.len(), 2);
+        let line = &lyric.lines[0];
+        assert_eq!(line.content, "Hello world (ooh ah)");
+        assert_eq!(line.translation.as_deref(), Some("你好世界"));
+        assert_eq!((line.begin, line.end), (1000, 3000));
+        assert_eq!(line.sung_chars(1600), 6.0);
+        assert_eq!(lyric.lines[1].content, "Duet");
+    }
+
+    #[test]
+    fn test_metadata() {
+        let lyric = Lyric::from_str(
+            "[ti:Song Title]\n[ar:Some Artist]\n[al:Album]\n[by:someone]\n\
+             [length: 03:45]\n[offset:+500]\n[00:01.00]first\n[00:02.00]second\n",
+        );
+        assert_eq!(
+            lyric.metadata,
+            LyricMetadata {
+                title: Some("Song Title".to_owned()),
+                artist: Some("Some Artist".to_owned()),
+                album: Some("Album".to_owned()),
+                by: Some("someone".to_owned()),
+                length: Some(225_000),
+                offset: 500,
+            }
+        );
+        assert_eq!((lyric.lines[0].begin, lyric.lines[0].end), (500, 1500));
+        assert_eq!(lyric.lines[1].begin, 1500);
+        assert!(lyric
+            .metadata
+            .matches(Some("song title"), Some("Some Artist feat. X")));
+        assert!(!lyric.metadata.matches(Some("Another Song"), None));
+
+        let lyric = Lyric::from_str("[offset:-250]\n[00:01.00]first\n");
+        assert_eq!(lyric.lines[0].begin, 1250);
+    }
+
+    #[test]
+    fn test_enhanced_lrc() {
+        let source = include_str!("fixtures/enhanced.lrc");
+        let lyric = Lyric::from_str(source);
+        assert_eq!(lyric.lines.len(), 4);
+        let line = &lyric.lines[0];
+        assert_eq!(line.content, "Never gonna give you up");
+        assert_eq!(line.words.len(), 5);
+        assert_eq!(line.words[0].content, "Never ");
+        assert_eq!((line.words[4].begin, line.words[4].end), (13500, 14000));
+        assert_eq!(line.translation.as_deref(), Some("绝不会放弃你"));
+        // The last word has no closing tag and runs until the next line.
+        let line = &lyric.lines[1];
+        assert_eq!(line.content, "Never gonna let you down");
+        assert_eq!(line.words[4].end, 18000);
+        assert_eq!(lyric.lines[2].content, "Plain line");
+        assert!(lyric.lines[2].words.is_empty());
+        let line = &lyric.lines[3];
+        assert_eq!(line.content, "I <3 you");
+        assert_eq!(line.words[0].content, "I <3 ");
+        assert_eq!((line.words[1].begin, line.words[1].end), (20500, 21000));
+        assert_eq!(
+            lyric.to_enhanced_lrc(),
+            include_str!("fixtures/enhanced.out.lrc")
+        );
+        let reparsed = Lyric::from_str(&lyric.to_enhanced_lrc());
+        assert_eq!(reparsed.lines, lyric.lines);
+    }
+
+    #[test]
+    fn test_yrc() {
+        let lyric = Lyric::parse(
+            "{\"t\":0,\"c\":[{\"tx\":\"作词: 某人\"}]}\n\
+             [1000,2000](1000,500,0)Never (1500,700,0)gonna (2200,800,0)give\n\
+             [4000,1000](4000,1000,0)up\n",
+            LyricFormat::Yrc,
+        )
+        .unwrap();
+        assert_eq!(lyric.lines.len(), 2);
+        assert_eq!(lyric.lines[0].content, "Never gonna give");
+        assert_eq!(lyric.lines[0].words.len(), 3);
+        assert_eq!((lyric.lines[0].begin, lyric.lines[0].end), (1000, 3000));
+        assert_eq!(lyric.lines[0].sung_chars(1500), 6.0);
+    }
+
+    #[test]
+    fn test_qrc() {
+        let qrc = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+                   <QrcInfos>\n\
+                   <QrcHeadInfo SaveTime=\"1\" Version=\"100\"/>\n\
+                   <LyricInfo LyricCount=\"1\">\n\
+                   <Lyric_1 LyricType=\"1\" LyricContent=\"[ti:Song]\n\
+                   [1000,2000]Rock(1000,1000)&amp;(2000,500)Roll(2500,500)\n\
+                   [4000,500]End(4000,500)\n\
+                   \"/>\n\
+                   </LyricInfo>\n\
+                   </QrcInfos>";
+        let lyric = Lyric::parse(qrc, LyricFormat::Qrc).unwrap();
+        assert_eq!(lyric.lines.len(), 2);
+        assert_eq!(lyric.lines[0].content, "Rock&Roll");
+        assert_eq!(lyric.lines[0].words[1].begin, 2000);
+        assert_eq!(lyric.lines[1].content, "End");
+    }
+
+    #[test]
+    fn test_krc() {
+        let krc = LyricFormat::Krc
+            .decode(include_bytes!("fixtures/sample.krc"), None)
+            .unwrap();
+        let lyric = Lyric::parse(&krc, LyricFormat::Krc).unwrap();
+        assert_eq!(lyric.metadata.title.as_deref(), Some("晴天"));
+        assert_eq!(lyric.lines.len(), 2);
+        assert_eq!(lyric.lines[0].content, "故事的小黄花");
+        assert_eq!(lyric.lines[0].words[1].begin, 30010);
+        assert_eq!(lyric.lines[1].end, 35050);
+        assert_eq!(
+            lyric.lines[0].translation.as_deref(),
+            Some("The little yellow flower")
+        );
+
+        let lyric =
+            Lyric::from_krc("[1000,2000]<0,1000,0>Hello <1000,1000,0>world \n[4000,500]End");
+        assert_eq!(lyric.lines[0].content, "Hello world");
+        assert_eq!(lyric.lines[0].words[1].end, 3000);
+        assert_eq!(lyric.lines[1].content, "End");
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            LyricFormat::from_path(Path::new("a/Song.TTML")),
+            Some(LyricFormat::Ttml)
+        );
+        assert_eq!(LyricFormat::from_path(Path::new("Song.mp3")), None);
+    }
+
+    #[test]
+    fn test_word_timing() {
+        let lines = amll_lyric::yrc::parse_yrc("[1000,1000](1000,500,0)Hello (1500,500,0)world");
+        let lyric = Lyric::from_amll(lines);
+        let line = &lyric.lines[0];
+        assert_eq!(line.content, "Hello world");
+        assert_eq!(line.words.len(), 2);
+        assert_eq!(line.words[1].begin, 1500);
+        assert_eq!(line.sung_chars(1750), 8.5);
+        let lyric = Lyric::from_str("[00:01.00]a\n[00:02.00]<00:02.00>c <00:02.50>d\n");
+        let word = &lyric.lines[1].words[1];
+        assert_eq!((word.begin, word.end), (2500, 0));
+        assert_eq!(lyric.lines[1].sung_chars(2500), 2.0);
+        assert!(lyric.lines[1].sung_chars(3000) < 3.0);
+        assert!(lyric.to_enhanced_lrc().ends_with("<00:02.50>d\n"));
     }
 }
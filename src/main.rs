@@ -1,22 +1,66 @@
 #![allow(dead_code)]
 
+use std::path::PathBuf;
+
 use clap::Parser;
 use config::Config;
-use log::info;
+use export::ExportFormat;
+use log::{error, info};
+use lyric::LyricFormat;
 use ui::DesktopLyricApp;
 
+mod cache;
+mod charset;
+mod cli;
 mod config;
+mod embedded;
+mod export;
 mod font;
 mod fuo;
+mod krc;
+mod library;
 mod lyric;
+mod provider;
 mod serve;
 mod spotify;
+mod state;
 mod ui;
 
 #[derive(clap::Parser)]
 struct Args {
     #[arg(help = "Config file path", short = 'c')]
     config: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Convert a lyric file, or a Spotify track (`spotify:<track id>`), to another format
+    Convert {
+        #[arg(help = "Lyric file or spotify:<track id>")]
+        input: String,
+        #[arg(help = "Output file, `-` for stdout")]
+        output: PathBuf,
+        #[arg(long, help = "Input format, guessed from the extension by default")]
+        from: Option<LyricFormat>,
+        #[arg(long, help = "Output format, guessed from the extension by default")]
+        to: Option<ExportFormat>,
+    },
+    /// Check lyric files for unsorted, duplicate and badly timed lines
+    Lint {
+        #[arg(required = true, help = "Lyric files")]
+        files: Vec<PathBuf>,
+        #[arg(long, help = "Rewrite files with problems (LRC and TTML only)")]
+        fix: bool,
+        #[arg(long, help = "Remove credit lines such as `作词 : …`")]
+        strip_credits: bool,
+    },
+    /// List the cached online lyrics
+    Cache {
+        #[arg(long, help = "Remove every cached lyric")]
+        clear: bool,
+    },
 }
 
 fn main() -> eframe::Result {
@@ -28,5 +72,25 @@ fn main() -> eframe::Result {
     } else {
         Config::init()
     };
-    DesktopLyricApp::run(config, config_path)
+    let result = match args.command {
+        Some(Command::Convert {
+            input,
+            output,
+            from,
+            to,
+        }) => cli::convert(&config, &input, &output, from, to),
+        Some(Command::Lint {
+            files,
+            fix,
+            strip_credits,
+        }) => cli::lint(&config, &files, fix, strip_credits),
+        Some(Command::Cache { clear }) => cli::cache(&config, clear),
+        None => return DesktopLyricApp::run(config, config_path),
+    };
+    if let Err(e) = result {
+        error!("{}", e);
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    Ok(())
 }
@@ -0,0 +1,140 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use log::debug;
+use serde::Deserialize;
+
+use super::{agent, candidate_matches, parse_json, LyricProvider, TrackInfo};
+use crate::krc;
+use crate::lyric::{Lyric, LyricMetadata};
+
+/// Kugou's lyric service. Lyrics come as encrypted, base64 encoded KRC.
+pub struct KugouProvider<'a> {
+    pub base_url: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Candidate {
+    id: String,
+    accesskey: String,
+    song: Option<String>,
+    singer: Option<String>,
+    /// Length in milliseconds.
+    duration: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct DownloadResponse {
+    content: Option<String>,
+}
+
+impl LyricProvider for KugouProvider<'_> {
+    fn fetch(&self, track: &TrackInfo) -> Result<Option<Lyric>, String> {
+        let base_url = self.base_url.trim_end_matches('/');
+        let keyword = if track.artists.is_empty() {
+            track.title.clone()
+        } else {
+            format!("{} - {}", track.artists.join("、"), track.title)
+        };
+        let search: SearchResponse = parse_json(
+            agent()
+                .get(&format!("{}/search", base_url))
+                .query("ver", "1")
+                .query("man", "yes")
+                .query("client", "pc")
+                .query("keyword", &keyword)
+                .query("duration", &track.length.unwrap_or_default().to_string())
+                .query("hash", "")
+                .call()
+                .map_err(|e| e.to_string())?,
+        )?;
+        let Some(candidate) = search
+            .candidates
+            .into_iter()
+            .find(|v| candidate_matches(v.song.as_deref(), v.singer.as_deref(), v.duration, track))
+        else {
+            return Ok(None);
+        };
+        debug!("Kugou lyric: {:?} ({})", candidate.song, candidate.id);
+        let download: DownloadResponse = parse_json(
+            agent()
+                .get(&format!("{}/download", base_url))
+                .query("ver", "1")
+                .query("client", "pc")
+                .query("id", &candidate.id)
+                .query("accesskey", &candidate.accesskey)
+                .query("fmt", "krc")
+                .query("charset", "utf8")
+                .call()
+                .map_err(|e| e.to_string())?,
+        )?;
+        let Some(content) = download.content.filter(|v| !v.is_empty()) else {
+            return Ok(None);
+        };
+        let bytes = BASE64_STANDARD
+            .decode(content.trim())
+            .map_err(|e| e.to_string())?;
+        let lyric = Lyric::from_krc(&krc::decrypt(&bytes)?);
+        if lyric.lines.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Lyric {
+            metadata: LyricMetadata {
+                title: candidate.song.or(lyric.metadata.title),
+                artist: candidate.singer.or(lyric.metadata.artist),
+                length: candidate.duration.or(lyric.metadata.length),
+                ..lyric.metadata
+            },
+            ..lyric
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::tests::mock_server;
+
+    #[test]
+    fn test_fetch() {
+        let base_url = mock_server(&[
+            (
+                "/search",
+                r#"{"status":200,"candidates":[
+                    {"id":"1","accesskey":"A","song":"晴天","singer":"周杰伦","duration":120000},
+                    {"id":"2","accesskey":"B","song":"晴天","singer":"周杰伦","duration":269000}
+                ]}"#,
+            ),
+            ("/download", include_str!("../fixtures/kugou_download.json")),
+        ]);
+        let track = TrackInfo {
+            title: "晴天".to_owned(),
+            artists: vec!["周杰伦".to_owned()],
+            length: Some(269_400),
+            ..Default::default()
+        };
+        let provider = KugouProvider {
+            base_url: &base_url,
+        };
+        let lyric = provider.fetch(&track).unwrap().unwrap();
+        assert_eq!(lyric.lines.len(), 2);
+        assert_eq!(lyric.lines[0].content, "故事的小黄花");
+        assert_eq!(lyric.lines[0].words.len(), 2);
+        assert_eq!((lyric.lines[0].begin, lyric.lines[0].end), (29010, 31910));
+        assert_eq!(
+            lyric.lines[1].translation.as_deref(),
+            Some("Has floated since the year it was born")
+        );
+        assert_eq!(lyric.metadata.length, Some(269_000));
+
+        let track = TrackInfo {
+            length: Some(200_000),
+            ..track
+        };
+        assert!(provider.fetch(&track).unwrap().is_none());
+    }
+}
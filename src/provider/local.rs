@@ -0,0 +1,184 @@
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+use log::{error, info, warn};
+
+use super::{LyricProvider, TrackInfo};
+use crate::config::{Config, EmbeddedLyric};
+use crate::embedded::read_embedded_lyric;
+use crate::library::LyricLibrary;
+use crate::lyric::Lyric;
+
+/// Lyric files in `lyric_dirs` and lyrics embedded in the track's own tags.
+pub struct LocalProvider<'a> {
+    pub config: &'a Config,
+    pub library: &'a LyricLibrary,
+}
+
+impl LyricProvider for LocalProvider<'_> {
+    /// Files can be found by the track's path or trackid even without a title.
+    fn applies(&self, _track: &TrackInfo) -> bool {
+        true
+    }
+
+    fn fetch(&self, track: &TrackInfo) -> Result<Option<Lyric>, String> {
+        let find_lyric_file = || find_lyric_file(track, self.config, self.library);
+        let read_embedded_lyric = || track.path.as_deref().and_then(read_embedded_lyric);
+        Ok(match self.config.embedded_lyric {
+            EmbeddedLyric::Prefer => read_embedded_lyric().or_else(find_lyric_file),
+            EmbeddedLyric::Fallback => find_lyric_file().or_else(read_embedded_lyric),
+            EmbeddedLyric::Ignore => find_lyric_file(),
+        })
+    }
+}
+
+/// Tries `lyric_templates` in every lyric directory, then looks for a lyric file named after
+/// the audio file in the library.
+fn find_lyric_file(info: &TrackInfo, config: &Config, library: &LyricLibrary) -> Option<Lyric> {
+    let templated = config
+        .lyric_templates
+        .iter()
+        .filter_map(|v| expand_template(v, info))
+        .find_map(|v| library.get(&v));
+    let path = templated.or_else(|| {
+        let file_stem = info.path.as_deref()?.file_stem()?.to_str()?;
+        if config.fuzzy {
+            library.search(file_stem)
+        } else {
+            library.find(file_stem, info).into_iter().next()
+        }
+    });
+    let lyric = load_lyric(&path?, config)?;
+    if lyric.metadata.matches(Some(&info.title), info.artist()) {
+        return Some(lyric);
+    }
+    warn!(
+        "Lyric tags ({:?} - {:?}) don't match the playing track",
+        lyric.metadata.artist, lyric.metadata.title
+    );
+    // A fuzzy match is only a guess, so don't trust it when the tags disagree.
+    if config.fuzzy {
+        return None;
+    }
+    Some(lyric)
+}
+
+/// Fills `{artist}` (the first one), `{artists}`, `{album}`, `{title}` and `{stem}` (of the
+/// audio file) in `template`. `None` when the track lacks one of them, or when a tag such as
+/// `..` would leave the lyric directory.
+fn expand_template(template: &str, track: &TrackInfo) -> Option<PathBuf> {
+    let stem = track
+        .path
+        .as_deref()
+        .and_then(Path::file_stem)
+        .map(|v| v.to_string_lossy().into_owned());
+    let values = [
+        ("{artist}", track.artist().map(str::to_owned)),
+        ("{artists}", Some(track.artists.join(", "))),
+        ("{album}", track.album.clone()),
+        ("{title}", Some(track.title.clone())),
+        ("{stem}", stem),
+    ];
+    let mut path = template.to_owned();
+    for (key, value) in values {
+        if !path.contains(key) {
+            continue;
+        }
+        let value = value.filter(|v| !v.trim().is_empty())?;
+        // Tags such as `AC/DC` must not add directories.
+        path = path.replace(key, &value.replace(['/', '\0'], "_"));
+    }
+    let path = PathBuf::from(path);
+    let escapes = path
+        .components()
+        .any(|v| matches!(v, Component::CurDir | Component::ParentDir));
+    (!escapes).then_some(path)
+}
+
+fn load_lyric(path: &Path, config: &Config) -> Option<Lyric> {
+    let encoding = config.lyric_encoding.as_deref();
+    let mut lyric = match Lyric::from_file(path, encoding) {
+        Ok(lyric) => lyric,
+        Err(e) => {
+            error!("Failed to load {}: {}", path.to_string_lossy(), e);
+            return None;
+        }
+    };
+    info!("Loaded lyric: {}", path.to_string_lossy());
+    if let Some(sidecar) = find_translation_sidecar(path, config.translation_language.as_deref()) {
+        info!("Loaded translation: {}", sidecar.to_string_lossy());
+        if let Ok(translation) = Lyric::from_file(&sidecar, encoding) {
+            lyric.merge_translation(&translation);
+        }
+    }
+    Some(lyric)
+}
+
+/// Looks for `<stem>.<lang>.lrc` next to `<stem>.lrc`, preferring `language`, then `trans`.
+fn find_translation_sidecar(path: &Path, language: Option<&str>) -> Option<PathBuf> {
+    let stem = path.file_stem()?.to_str()?;
+    let dir = path.parent()?;
+    if let Some(language) = language {
+        let sidecar = dir.join(format!("{}.{}.lrc", stem, language));
+        if sidecar.exists() {
+            return Some(sidecar);
+        }
+    }
+    let prefix = format!("{}.", stem);
+    let mut sidecars = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|v| v.ok())
+        .map(|v| v.path())
+        .filter(|v| v.extension() == Some(OsStr::new("lrc")))
+        .filter(|v| {
+            v.file_stem()
+                .and_then(|v| v.to_str())
+                .and_then(|v| v.strip_prefix(&prefix))
+                .is_some_and(|v| !v.is_empty() && !v.contains('.'))
+        })
+        .collect::<Vec<PathBuf>>();
+    sidecars.sort_by_key(|v| {
+        v.file_stem()
+            .map(|v| !v.to_string_lossy().ends_with(".trans"))
+    });
+    sidecars.into_iter().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_template() {
+        let track = TrackInfo {
+            title: "Back in Black".to_owned(),
+            artists: vec!["AC/DC".to_owned(), "Other".to_owned()],
+            path: Some(PathBuf::from("/music/06 Back in Black.flac")),
+            ..Default::default()
+        };
+        assert_eq!(
+            expand_template("{artist} - {title}.lrc", &track),
+            Some(PathBuf::from("AC_DC - Back in Black.lrc"))
+        );
+        assert_eq!(
+            expand_template("{artists}/{stem}.lrc", &track),
+            Some(PathBuf::from("AC_DC, Other/06 Back in Black.lrc"))
+        );
+        assert_eq!(
+            expand_template("{artist}/{album}/{title}.lrc", &track),
+            None
+        );
+        let track = TrackInfo {
+            path: None,
+            ..track
+        };
+        assert_eq!(expand_template("{stem}.lrc", &track), None);
+        let track = TrackInfo {
+            artists: vec!["..".to_owned()],
+            album: Some(".".to_owned()),
+            ..track
+        };
+        assert_eq!(expand_template("{artist}/{title}.lrc", &track), None);
+        assert_eq!(expand_template("{album}/{title}.lrc", &track), None);
+    }
+}
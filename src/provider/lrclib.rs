@@ -0,0 +1,154 @@
+use log::debug;
+use serde::Deserialize;
+
+use super::{agent, parse_json, LyricProvider, TrackInfo};
+use crate::lyric::Lyric;
+
+/// Largest difference, in seconds, between the track and a search result's duration.
+const DURATION_TOLERANCE: f64 = 5.0;
+
+/// [LRCLIB](https://lrclib.net), or a self-hosted instance of it.
+pub struct LrclibProvider<'a> {
+    pub base_url: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    track_name: Option<String>,
+    artist_name: Option<String>,
+    album_name: Option<String>,
+    duration: Option<f64>,
+    #[serde(default)]
+    instrumental: bool,
+    plain_lyrics: Option<String>,
+    synced_lyrics: Option<String>,
+}
+
+impl LyricProvider for LrclibProvider<'_> {
+    fn fetch(&self, track: &TrackInfo) -> Result<Option<Lyric>, String> {
+        let base_url = self.base_url.trim_end_matches('/');
+        let artist = track.artists.join(", ");
+        // `/api/get` needs the duration and matches it within a couple of seconds.
+        if let Some(length) = track.length {
+            let request = get(&format!("{}/api/get", base_url))
+                .query("track_name", &track.title)
+                .query("artist_name", &artist)
+                .query("album_name", track.album.as_deref().unwrap_or_default())
+                .query("duration", &(length / 1000).to_string());
+            match request.call() {
+                Ok(resp) => {
+                    let record: Record = parse_json(resp)?;
+                    if let Some(lyric) = record.into_lyric() {
+                        return Ok(Some(lyric));
+                    }
+                }
+                Err(ureq::Error::Status(404, _)) => debug!("No exact LRCLIB match"),
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        let records: Vec<Record> = parse_json(
+            get(&format!("{}/api/search", base_url))
+                .query("track_name", &track.title)
+                .query("artist_name", &artist)
+                .call()
+                .map_err(|e| e.to_string())?,
+        )?;
+        let length = track.length.map(|v| v as f64 / 1000.0);
+        let mut records = records
+            .into_iter()
+            .filter(|v| match (v.duration, length) {
+                (Some(a), Some(b)) => (a - b).abs() <= DURATION_TOLERANCE,
+                _ => true,
+            })
+            .collect::<Vec<Record>>();
+        // Synced lyrics first; the sort is stable, keeping LRCLIB's ranking otherwise.
+        records.sort_by_key(|v| v.synced_lyrics.as_deref().is_none_or(str::is_empty));
+        Ok(records.into_iter().find_map(Record::into_lyric))
+    }
+}
+
+impl Record {
+    /// Synced lyrics if there are any, otherwise the plain text.
+    fn into_lyric(self) -> Option<Lyric> {
+        if self.instrumental {
+            return None;
+        }
+        let mut lyric = match (self.synced_lyrics, self.plain_lyrics) {
+            (Some(synced), _) if !synced.trim().is_empty() => Lyric::from_str(&synced),
+            (_, Some(plain)) if !plain.trim().is_empty() => Lyric::unsynced(&plain),
+            _ => return None,
+        };
+        let metadata = &mut lyric.metadata;
+        metadata.title = metadata.title.take().or(self.track_name);
+        metadata.artist = metadata.artist.take().or(self.artist_name);
+        metadata.album = metadata.album.take().or(self.album_name);
+        metadata.length = metadata
+            .length
+            .or(self.duration.map(|v| (v * 1000.0) as u64));
+        Some(lyric)
+    }
+}
+
+/// LRCLIB asks clients to identify themselves.
+fn get(url: &str) -> ureq::Request {
+    agent().get(url).set(
+        "User-Agent",
+        concat!("desktop_lyric/", env!("CARGO_PKG_VERSION")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lyric::LyricKind;
+    use crate::provider::tests::mock_server;
+
+    #[test]
+    fn test_fetch() {
+        let track = TrackInfo {
+            title: "Song".to_owned(),
+            artists: vec!["Artist".to_owned()],
+            length: Some(200_000),
+            ..Default::default()
+        };
+        let base_url = mock_server(&[(
+            "/api/get",
+            r#"{"trackName":"Song","artistName":"Artist","duration":200,"instrumental":false,
+                "plainLyrics":"first\nsecond","syncedLyrics":"[00:01.00]first\n[00:02.00]second"}"#,
+        )]);
+        let lyric = LrclibProvider {
+            base_url: &base_url,
+        }
+        .fetch(&track)
+        .unwrap()
+        .unwrap();
+        assert_eq!(lyric.kind, LyricKind::Synced);
+        assert_eq!(lyric.lines[1].begin, 2000);
+        assert_eq!(lyric.metadata.artist.as_deref(), Some("Artist"));
+
+        let base_url = mock_server(&[(
+            "/api/search",
+            r#"[{"duration":100,"syncedLyrics":"[00:01.00]wrong length"},
+                {"duration":201,"plainLyrics":"plain only"},
+                {"duration":199,"plainLyrics":"x","syncedLyrics":"[00:01.00]synced"}]"#,
+        )]);
+        let provider = LrclibProvider {
+            base_url: &base_url,
+        };
+        let lyric = provider.fetch(&track).unwrap().unwrap();
+        assert_eq!(lyric.lines[0].content, "synced");
+        let track = TrackInfo {
+            length: None,
+            ..track
+        };
+        let lyric = provider.fetch(&track).unwrap().unwrap();
+        assert_eq!(lyric.lines[0].content, "wrong length");
+
+        let base_url = mock_server(&[("/api/search", "[]")]);
+        let provider = LrclibProvider {
+            base_url: &base_url,
+        };
+        assert!(provider.fetch(&track).unwrap().is_none());
+    }
+}
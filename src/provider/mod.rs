@@ -0,0 +1,353 @@
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use log::{debug, error, info};
+use mpris::Metadata;
+use serde::{Deserialize, Serialize};
+
+use crate::cache::LyricCache;
+use crate::config::Config;
+use crate::fuo::FuoClient;
+use crate::library::LyricLibrary;
+use crate::lyric::{Lyric, LyricMetadata};
+use crate::spotify::fetch_spotify_lyric;
+
+mod kugou;
+mod local;
+mod lrclib;
+mod netease;
+mod qqmusic;
+
+pub use kugou::KugouProvider;
+pub use local::LocalProvider;
+pub use lrclib::LrclibProvider;
+pub use netease::NeteaseProvider;
+pub use qqmusic::QQMusicProvider;
+
+/// Longest an online provider may spend on one request, so a stalled server can't hold up
+/// the serve loop.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest difference, in milliseconds, between the track and a search result's length.
+const DURATION_TOLERANCE: u64 = 5000;
+/// Search results looked at.
+const SEARCH_LIMIT: &str = "10";
+
+/// A source of lyrics. `Ok(None)` means the source has no lyric for the track, so the next
+/// provider is tried.
+pub trait LyricProvider {
+    /// Whether the provider can look `track` up at all. Providers that don't apply are skipped
+    /// without touching the cache. Searching needs at least a title by default.
+    fn applies(&self, track: &TrackInfo) -> bool {
+        !track.title.is_empty()
+    }
+
+    fn fetch(&self, track: &TrackInfo) -> Result<Option<Lyric>, String>;
+}
+
+/// Providers that can be listed in the `providers` option, tried in order.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    /// Lyric files in `lyric_dirs` and lyrics embedded in the audio file.
+    Local,
+    /// The lyric FeelUOwn shows, when it is the player.
+    Feeluown,
+    /// Spotify's lyric service, for Spotify tracks. Needs the Spotify tokens.
+    Spotify,
+    /// LRCLIB at `lrclib_url`, searched by title, artist, album and length.
+    Lrclib,
+    /// NetEase Cloud Music through the NeteaseCloudMusicApi server at `netease_url`.
+    Netease,
+    /// QQ Music at `qq_music_url`, with word-level QRC lyrics.
+    #[serde(rename = "qqmusic")]
+    QQMusic,
+    /// Kugou at `kugou_url`, with word-level KRC lyrics.
+    Kugou,
+}
+
+impl ProviderKind {
+    /// Local files are read every time, so edits to them show up right away.
+    pub fn is_cached(&self) -> bool {
+        *self != ProviderKind::Local
+    }
+
+    pub fn provider<'a>(
+        &self,
+        config: &'a Config,
+        library: &'a LyricLibrary,
+    ) -> Box<dyn LyricProvider + 'a> {
+        match self {
+            ProviderKind::Local => Box::new(LocalProvider { config, library }),
+            ProviderKind::Feeluown => Box::new(FeelUOwnProvider),
+            ProviderKind::Spotify => Box::new(SpotifyProvider {
+                access_token: config.spotify_access_token.as_deref(),
+                client_token: config.spotify_client_token.as_deref(),
+            }),
+            ProviderKind::Lrclib => Box::new(LrclibProvider {
+                base_url: &config.lrclib_url,
+            }),
+            ProviderKind::Netease => Box::new(NeteaseProvider {
+                base_url: &config.netease_url,
+            }),
+            ProviderKind::QQMusic => Box::new(QQMusicProvider {
+                base_url: &config.qq_music_url,
+            }),
+            ProviderKind::Kugou => Box::new(KugouProvider {
+                base_url: &config.kugou_url,
+            }),
+        }
+    }
+}
+
+/// The playing track as far as providers are concerned.
+#[derive(Debug, Clone, Default)]
+pub struct TrackInfo {
+    /// MPRIS bus name of the player, without the `org.mpris.MediaPlayer2.` prefix.
+    pub player: String,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    /// Length in milliseconds.
+    pub length: Option<u64>,
+    /// Local audio file, from a `file://` URL.
+    pub path: Option<PathBuf>,
+    /// MPRIS trackid, e.g. `/com/spotify/track/<id>`.
+    pub track_id: Option<String>,
+}
+
+impl TrackInfo {
+    pub fn from_metadata(player: &str, metadata: &Metadata) -> Self {
+        Self {
+            player: player.to_owned(),
+            title: metadata.title().unwrap_or_default().to_owned(),
+            artists: metadata
+                .artists()
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::to_owned)
+                .collect(),
+            album: metadata.album_name().map(str::to_owned),
+            length: metadata.length().map(|v| v.as_millis() as u64),
+            path: metadata
+                .url()
+                .and_then(|v| url::Url::parse(v).ok())
+                .and_then(|v| v.to_file_path().ok()),
+            track_id: metadata.track_id().map(|v| v.to_string()),
+        }
+    }
+
+    pub fn artist(&self) -> Option<&str> {
+        self.artists.first().map(String::as_str)
+    }
+}
+
+/// Tries the configured providers in order and returns the first non-empty lyric, going
+/// through the cache for online providers.
+pub fn fetch_lyric(config: &Config, library: &LyricLibrary, track: &TrackInfo) -> Option<Lyric> {
+    let cache = LyricCache::new(config);
+    for kind in config.providers.iter() {
+        let provider = kind.provider(config, library);
+        if !provider.applies(track) {
+            debug!("The {:?} provider doesn't apply to the track", kind);
+            continue;
+        }
+        let cache = cache.as_ref().filter(|_| kind.is_cached());
+        let result = match cache.and_then(|v| v.get(*kind, track)) {
+            Some(lyric) => {
+                debug!("Cached result of the {:?} provider", kind);
+                Ok(lyric)
+            }
+            None => {
+                let result = provider.fetch(track);
+                // Errors are usually network trouble, so they are not remembered.
+                if let (Some(cache), Ok(lyric)) = (cache, &result) {
+                    cache.put(*kind, track, lyric.as_ref().filter(|v| !v.lines.is_empty()));
+                }
+                result
+            }
+        };
+        match result {
+            Ok(Some(lyric)) if !lyric.lines.is_empty() => {
+                info!("Using lyric from the {:?} provider", kind);
+                return Some(lyric);
+            }
+            Ok(_) => debug!("No lyric from the {:?} provider", kind),
+            Err(e) => error!("{:?} provider failed: {}", kind, e),
+        }
+    }
+    None
+}
+
+/// The HTTP agent shared by the online providers.
+fn agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+    AGENT.get_or_init(|| ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build())
+}
+
+/// Whether a search result is the playing track: the same length, in milliseconds, within the
+/// tolerance, and the same title and artist as far as known.
+fn candidate_matches(
+    title: Option<&str>,
+    artist: Option<&str>,
+    length: Option<u64>,
+    track: &TrackInfo,
+) -> bool {
+    if let (Some(a), Some(b)) = (length, track.length) {
+        if a.abs_diff(b) > DURATION_TOLERANCE {
+            return false;
+        }
+    }
+    let metadata = LyricMetadata {
+        title: title.map(str::to_owned),
+        artist: artist.map(str::to_owned),
+        ..Default::default()
+    };
+    metadata.matches(Some(&track.title), track.artist())
+}
+
+/// Artist names of a search result as one tag value, `None` when there are none.
+fn join_artists<'a>(names: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    Some(names.into_iter().collect::<Vec<&str>>().join(", ")).filter(|v| !v.is_empty())
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(resp: ureq::Response) -> Result<T, String> {
+    serde_json::from_str(&resp.into_string().map_err(|e| e.to_string())?).map_err(|e| e.to_string())
+}
+
+pub struct FeelUOwnProvider;
+
+impl LyricProvider for FeelUOwnProvider {
+    /// fuo only knows about the song FeelUOwn itself is playing.
+    fn applies(&self, track: &TrackInfo) -> bool {
+        track.player.to_lowercase().contains("feeluown")
+    }
+
+    fn fetch(&self, track: &TrackInfo) -> Result<Option<Lyric>, String> {
+        if !self.applies(track) {
+            return Ok(None);
+        }
+        // No answer means fuo isn't reachable, which is not the same as the song having no lyric.
+        let lyric = FuoClient
+            .lyric()
+            .ok_or_else(|| "fuo is not running".to_owned())?;
+        Ok(Some(Lyric::from_text(&lyric)))
+    }
+}
+
+pub struct SpotifyProvider<'a> {
+    pub access_token: Option<&'a str>,
+    pub client_token: Option<&'a str>,
+}
+
+impl LyricProvider for SpotifyProvider<'_> {
+    /// Needs both tokens and a Spotify trackid.
+    fn applies(&self, track: &TrackInfo) -> bool {
+        self.access_token.is_some() && self.client_token.is_some() && spotify_id(track).is_some()
+    }
+
+    fn fetch(&self, track: &TrackInfo) -> Result<Option<Lyric>, String> {
+        let (Some(access_token), Some(client_token), Some(id)) =
+            (self.access_token, self.client_token, spotify_id(track))
+        else {
+            return Ok(None);
+        };
+        debug!("Trackid: {}", id);
+        Ok(fetch_spotify_lyric(
+            access_token,
+            client_token,
+            Some(id.to_owned()),
+        ))
+    }
+}
+
+/// `<id>` of an MPRIS trackid such as `/com/spotify/track/<id>`.
+fn spotify_id(track: &TrackInfo) -> Option<&str> {
+    track
+        .track_id
+        .as_deref()
+        .filter(|v| v.contains("/com/spotify/track/"))
+        .and_then(|v| v.split('/').next_back())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// Answers every request from `routes`, keyed by path without the query, and 404 otherwise.
+    pub(crate) fn mock_server(routes: &'static [(&'static str, &'static str)]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                let path = request.split([' ', '?']).nth(1).unwrap_or_default();
+                let (status, body) = match routes.iter().find(|v| v.0 == path) {
+                    Some((_, body)) => ("200 OK", *body),
+                    None => ("404 Not Found", "{}"),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_providers_skip_other_tracks() {
+        let track = TrackInfo {
+            player: "deadbeef".to_owned(),
+            title: "Song".to_owned(),
+            track_id: Some("/org/mpris/MediaPlayer2/Track/1".to_owned()),
+            ..Default::default()
+        };
+        assert!(!FeelUOwnProvider.applies(&track));
+        assert!(matches!(FeelUOwnProvider.fetch(&track), Ok(None)));
+        let spotify = SpotifyProvider {
+            access_token: Some("token"),
+            client_token: Some("token"),
+        };
+        assert!(!spotify.applies(&track));
+        assert!(matches!(spotify.fetch(&track), Ok(None)));
+        let spotify_track = TrackInfo {
+            track_id: Some("/com/spotify/track/abc".to_owned()),
+            ..track.clone()
+        };
+        assert!(spotify.applies(&spotify_track));
+        let no_title = TrackInfo {
+            title: String::new(),
+            ..track.clone()
+        };
+        assert!(!(LrclibProvider {
+            base_url: "http://localhost"
+        })
+        .applies(&no_title));
+        let config: Config = serde_yaml::from_str(include_str!("../../config.yaml")).unwrap();
+        assert_eq!(
+            config.providers,
+            [
+                ProviderKind::Feeluown,
+                ProviderKind::Spotify,
+                ProviderKind::Local
+            ]
+        );
+        let config = Config {
+            providers: vec![ProviderKind::Feeluown, ProviderKind::Local],
+            cache_ttl_hours: 0,
+            ..config
+        };
+        let library = LyricLibrary::open(&[PathBuf::from("src/fixtures")]);
+        assert!(fetch_lyric(&config, &library, &track).is_none());
+    }
+}
@@ -0,0 +1,221 @@
+use log::debug;
+use serde::Deserialize;
+
+use super::{
+    agent, candidate_matches, join_artists, parse_json, LyricProvider, TrackInfo, SEARCH_LIMIT,
+};
+use crate::lyric::{Lyric, LyricMetadata};
+
+/// NetEase Cloud Music through a NeteaseCloudMusicApi compatible server.
+pub struct NeteaseProvider<'a> {
+    pub base_url: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    result: Option<SearchResult>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResult {
+    #[serde(default)]
+    songs: Vec<Song>,
+}
+
+/// `/cloudsearch` uses the short field names, `/search` the long ones.
+#[derive(Deserialize, Debug)]
+struct Song {
+    id: u64,
+    name: String,
+    #[serde(default, alias = "artists")]
+    ar: Vec<Named>,
+    #[serde(alias = "album")]
+    al: Option<Named>,
+    #[serde(alias = "duration")]
+    dt: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Named {
+    name: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct LyricResponse {
+    #[serde(default)]
+    pure_music: bool,
+    lrc: Option<LyricText>,
+    tlyric: Option<LyricText>,
+    yrc: Option<LyricText>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LyricText {
+    lyric: Option<String>,
+}
+
+impl LyricProvider for NeteaseProvider<'_> {
+    fn fetch(&self, track: &TrackInfo) -> Result<Option<Lyric>, String> {
+        let base_url = self.base_url.trim_end_matches('/');
+        let keywords = format!("{} {}", track.title, track.artists.join(" "));
+        let search: SearchResponse = parse_json(
+            agent()
+                .get(&format!("{}/cloudsearch", base_url))
+                .query("keywords", keywords.trim())
+                .query("type", "1")
+                .query("limit", SEARCH_LIMIT)
+                .call()
+                .map_err(|e| e.to_string())?,
+        )?;
+        let songs = search.result.map(|v| v.songs).unwrap_or_default();
+        let Some(song) = songs
+            .into_iter()
+            .find(|v| candidate_matches(Some(&v.name), v.artist().as_deref(), v.dt, track))
+        else {
+            return Ok(None);
+        };
+        debug!("NetEase song: {} ({})", song.name, song.id);
+        let lyric: LyricResponse = parse_json(
+            agent()
+                .get(&format!("{}/lyric/new", base_url))
+                .query("id", &song.id.to_string())
+                .call()
+                .map_err(|e| e.to_string())?,
+        )?;
+        Ok(lyric.into_lyric().map(|mut lyric| {
+            lyric.metadata = LyricMetadata {
+                artist: song.artist(),
+                title: Some(song.name),
+                album: song.al.and_then(|v| v.name),
+                length: song.dt,
+                ..lyric.metadata
+            };
+            lyric
+        }))
+    }
+}
+
+impl Song {
+    fn artist(&self) -> Option<String> {
+        join_artists(self.ar.iter().filter_map(|v| v.name.as_deref()))
+    }
+}
+
+impl LyricResponse {
+    /// Word-level `yrc` when there is one, otherwise the line-level `lrc`, with the `tlyric`
+    /// translation merged in.
+    fn into_lyric(self) -> Option<Lyric> {
+        if self.pure_music {
+            return None;
+        }
+        let text = |v: Option<LyricText>| v.and_then(|v| v.lyric).filter(|v| !v.trim().is_empty());
+        let mut lrc = text(self.lrc).map(|v| Lyric::from_str(&v));
+        let yrc = text(self.yrc)
+            .map(|v| Lyric::from_yrc(&v))
+            .filter(|v| !v.lines.is_empty());
+        if let (Some(lrc), Some(translation)) = (lrc.as_mut(), text(self.tlyric)) {
+            lrc.merge_translation(&Lyric::from_str(&translation));
+        }
+        let Some(mut yrc) = yrc else {
+            return lrc.filter(|v| !v.lines.is_empty());
+        };
+        if let Some(lrc) = lrc {
+            yrc.metadata = lrc.metadata.clone();
+            copy_translations(&lrc, &mut yrc);
+        }
+        Some(yrc)
+    }
+}
+
+/// `lrc` and `yrc` have the same lines but slightly different timestamps, so pair them up by
+/// position when the line counts agree, and by time otherwise.
+fn copy_translations(lrc: &Lyric, yrc: &mut Lyric) {
+    let lines = |lyric: &Lyric| lyric.lines.iter().filter(|v| !v.content.is_empty()).count();
+    if lines(lrc) == lines(yrc) {
+        let translations = lrc.lines.iter().filter(|v| !v.content.is_empty());
+        let targets = yrc.lines.iter_mut().filter(|v| !v.content.is_empty());
+        for (target, line) in targets.zip(translations) {
+            target.translation = line.translation.clone();
+        }
+        return;
+    }
+    let mut translation = lrc.clone();
+    translation.lines.retain(|v| v.translation.is_some());
+    for line in translation.lines.iter_mut() {
+        line.content = line.translation.take().unwrap();
+    }
+    yrc.merge_translation(&translation);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::tests::mock_server;
+
+    #[test]
+    fn test_fetch() {
+        let base_url = mock_server(&[
+            (
+                "/cloudsearch",
+                r#"{"result":{"songs":[
+                    {"id":1,"name":"晴天","ar":[{"name":"周杰伦"}],"al":{"name":"叶惠美"},"dt":120000},
+                    {"id":2,"name":"晴天","ar":[{"name":"周杰伦"}],"al":{"name":"叶惠美"},"dt":269000}
+                ]},"code":200}"#,
+            ),
+            (
+                "/lyric/new",
+                r#"{"lrc":{"lyric":"[ti:晴天]\n[00:29.00]故事的小黄花\n[00:32.00]从出生那年就飘着\n"},
+                    "tlyric":{"lyric":"[00:29.00]The little yellow flower\n"},
+                    "yrc":{"lyric":"{\"t\":0,\"c\":[{\"tx\":\"作词: \"}]}\n[29010,2900](29010,1000,0)故事(30010,1900,0)的小黄花\n[32050,3000](32050,3000,0)从出生那年就飘着\n"},
+                    "code":200}"#,
+            ),
+        ]);
+        let track = TrackInfo {
+            title: "晴天".to_owned(),
+            artists: vec!["周杰伦".to_owned()],
+            length: Some(269_500),
+            ..Default::default()
+        };
+        let lyric = NeteaseProvider {
+            base_url: &base_url,
+        }
+        .fetch(&track)
+        .unwrap()
+        .unwrap();
+        assert_eq!(lyric.lines.len(), 2);
+        assert_eq!(lyric.lines[0].content, "故事的小黄花");
+        assert_eq!(lyric.lines[0].words.len(), 2);
+        assert_eq!(
+            lyric.lines[0].translation.as_deref(),
+            Some("The little yellow flower")
+        );
+        assert_eq!(lyric.lines[1].translation, None);
+        assert_eq!(lyric.metadata.length, Some(269_000));
+        assert_eq!(lyric.metadata.album.as_deref(), Some("叶惠美"));
+
+        let track = TrackInfo {
+            length: Some(200_000),
+            ..track
+        };
+        let provider = NeteaseProvider {
+            base_url: &base_url,
+        };
+        assert!(provider.fetch(&track).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_into_lyric() {
+        let response: LyricResponse = serde_json::from_str(
+            r#"{"lrc":{"lyric":"[00:01.00]a\n[00:02.00]b\n"},"tlyric":{"lyric":"[00:02.00]乙\n"}}"#,
+        )
+        .unwrap();
+        let lyric = response.into_lyric().unwrap();
+        assert_eq!(lyric.lines[1].translation.as_deref(), Some("乙"));
+        let response = LyricResponse {
+            pure_music: true,
+            ..Default::default()
+        };
+        assert!(response.into_lyric().is_none());
+    }
+}
@@ -0,0 +1,253 @@
+use log::debug;
+use serde::Deserialize;
+
+use super::{
+    agent, candidate_matches, join_artists, parse_json, LyricProvider, TrackInfo, SEARCH_LIMIT,
+};
+use crate::lyric::{Lyric, LyricMetadata};
+
+/// QQ Music. Word-level lyrics come as encrypted QRC, with plain LRC as the fallback.
+pub struct QQMusicProvider<'a> {
+    pub base_url: &'a str,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchResponse {
+    data: Option<SearchData>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SearchData {
+    song: Option<SongList>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SongList {
+    #[serde(default)]
+    list: Vec<Song>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Song {
+    songid: u64,
+    songmid: String,
+    songname: String,
+    #[serde(default)]
+    singer: Vec<Singer>,
+    albumname: Option<String>,
+    /// Length in seconds.
+    interval: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Singer {
+    name: String,
+}
+
+/// Reply of the plain LRC endpoint, asked for without base64.
+#[derive(Deserialize, Debug)]
+struct LrcResponse {
+    lyric: Option<String>,
+    trans: Option<String>,
+}
+
+impl LyricProvider for QQMusicProvider<'_> {
+    fn fetch(&self, track: &TrackInfo) -> Result<Option<Lyric>, String> {
+        let keywords = format!("{} {}", track.title, track.artists.join(" "));
+        let search: SearchResponse = parse_json(
+            self.get("/soso/fcgi-bin/client_search_cp")
+                .query("w", keywords.trim())
+                .query("format", "json")
+                .query("p", "1")
+                .query("n", SEARCH_LIMIT)
+                .call()
+                .map_err(|e| e.to_string())?,
+        )?;
+        let songs = search
+            .data
+            .and_then(|v| v.song)
+            .map(|v| v.list)
+            .unwrap_or_default();
+        let Some(song) = songs.into_iter().find(|v| {
+            let length = v.interval.map(|v| v * 1000);
+            candidate_matches(Some(&v.songname), v.artist().as_deref(), length, track)
+        }) else {
+            return Ok(None);
+        };
+        debug!("QQ Music song: {} ({})", song.songname, song.songmid);
+        let lyric = match self.fetch_qrc(&song)? {
+            Some(lyric) => lyric,
+            None => match self.fetch_lrc(&song)? {
+                Some(lyric) => lyric,
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(Lyric {
+            metadata: LyricMetadata {
+                artist: song.artist(),
+                title: Some(song.songname),
+                album: song.albumname,
+                length: song.interval.map(|v| v * 1000),
+                ..lyric.metadata
+            },
+            ..lyric
+        }))
+    }
+}
+
+impl QQMusicProvider<'_> {
+    fn get(&self, path: &str) -> ureq::Request {
+        agent()
+            .get(&format!("{}{}", self.base_url.trim_end_matches('/'), path))
+            .set("Referer", "https://y.qq.com/")
+    }
+
+    /// Word-level QRC, decrypted, with the `contentts` translation merged in.
+    fn fetch_qrc(&self, song: &Song) -> Result<Option<Lyric>, String> {
+        let xml = self
+            .get("/qqmusic/fcgi-bin/lyric_download.fcg")
+            .query("version", "15")
+            .query("miniversion", "82")
+            .query("lrctype", "4")
+            .query("musicid", &song.songid.to_string())
+            .call()
+            .map_err(|e| e.to_string())?
+            .into_string()
+            .map_err(|e| e.to_string())?;
+        let decrypt = |tag| {
+            Some(amll_lyric::eqrc::decrypt_qrc_hex(
+                xml_text(&xml, tag)?.trim(),
+            ))
+            .filter(|v| !v.trim().is_empty())
+        };
+        let Some(qrc) = decrypt("content") else {
+            return Ok(None);
+        };
+        let mut lyric = Lyric::from_qrc(&qrc);
+        if lyric.lines.is_empty() {
+            return Ok(None);
+        }
+        if let Some(translation) = decrypt("contentts") {
+            lyric.merge_translation(&Lyric::from_str(&translation));
+        }
+        Ok(Some(lyric))
+    }
+
+    /// Line-level LRC for songs without QRC.
+    fn fetch_lrc(&self, song: &Song) -> Result<Option<Lyric>, String> {
+        let resp: LrcResponse = parse_json(
+            self.get("/lyric/fcgi-bin/fcg_query_lyric_new.fcg")
+                .query("songmid", &song.songmid)
+                .query("format", "json")
+                .query("nobase64", "1")
+                .call()
+                .map_err(|e| e.to_string())?,
+        )?;
+        // Without base64 the text comes HTML escaped, timestamps included (`[00&#58;29.01]`).
+        // Parsing decodes the rest, so only the colons are unescaped here.
+        let parse = |v: String| Lyric::from_str(&v.replace("&#58;", ":"));
+        let Some(mut lyric) = resp.lyric.map(parse) else {
+            return Ok(None);
+        };
+        if let Some(translation) = resp.trans.filter(|v| !v.trim().is_empty()) {
+            lyric.merge_translation(&parse(translation));
+        }
+        Ok(Some(lyric).filter(|v| !v.lines.is_empty()))
+    }
+}
+
+impl Song {
+    fn artist(&self) -> Option<String> {
+        join_artists(self.singer.iter().map(|v| v.name.as_str()))
+    }
+}
+
+/// Text of the first `<tag ...>` element, unwrapping `<![CDATA[...]]>`.
+fn xml_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}", tag);
+    let start = xml.match_indices(&open).find_map(|(i, _)| {
+        let rest = &xml[i + open.len()..];
+        if !rest.starts_with([' ', '>']) {
+            return None;
+        }
+        Some(i + open.len() + rest.find('>')? + 1)
+    })?;
+    let text = &xml[start..start + xml[start..].find(&format!("</{}>", tag))?];
+    Some(
+        text.trim()
+            .strip_prefix("<![CDATA[")
+            .and_then(|v| v.strip_suffix("]]>"))
+            .unwrap_or(text),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::tests::mock_server;
+
+    const SEARCH: &str = r#"{"code":0,"data":{"song":{"curnum":2,"list":[
+        {"albumname":"叶惠美","interval":269,"songid":97773,"songmid":"0039MnYb0qxYhV","songname":"晴天","singer":[{"name":"周杰伦"}]},
+        {"albumname":"Live","interval":300,"songid":1,"songmid":"live","songname":"晴天","singer":[{"name":"周杰伦"}]}
+    ]}}}"#;
+
+    #[test]
+    fn test_fetch() {
+        let base_url = mock_server(&[
+            ("/soso/fcgi-bin/client_search_cp", SEARCH),
+            (
+                "/qqmusic/fcgi-bin/lyric_download.fcg",
+                include_str!("../fixtures/qqmusic_lyric.xml"),
+            ),
+        ]);
+        let track = TrackInfo {
+            title: "晴天".to_owned(),
+            artists: vec!["周杰伦".to_owned()],
+            length: Some(269_400),
+            ..Default::default()
+        };
+        let provider = QQMusicProvider {
+            base_url: &base_url,
+        };
+        let lyric = provider.fetch(&track).unwrap().unwrap();
+        assert_eq!(lyric.lines.len(), 2);
+        assert_eq!(lyric.lines[0].content, "故事的小黄花");
+        assert_eq!(lyric.lines[0].words.len(), 2);
+        assert_eq!((lyric.lines[0].begin, lyric.lines[0].end), (29010, 31910));
+        assert_eq!(
+            lyric.lines[1].translation.as_deref(),
+            Some("Has floated since the year it was born")
+        );
+        assert_eq!(lyric.metadata.album.as_deref(), Some("叶惠美"));
+
+        let base_url = mock_server(&[
+            ("/soso/fcgi-bin/client_search_cp", SEARCH),
+            (
+                "/qqmusic/fcgi-bin/lyric_download.fcg",
+                "<!--<lyric><content><![CDATA[]]></content></lyric>-->",
+            ),
+            (
+                "/lyric/fcgi-bin/fcg_query_lyric_new.fcg",
+                r#"{"retcode":0,"lyric":"[00&#58;29.01]故事的小黄花\n","trans":"[00&#58;29.01]The little yellow flower\n"}"#,
+            ),
+        ]);
+        let provider = QQMusicProvider {
+            base_url: &base_url,
+        };
+        let lyric = provider.fetch(&track).unwrap().unwrap();
+        assert_eq!(lyric.lines[0].begin, 29010);
+        assert_eq!(
+            lyric.lines[0].translation.as_deref(),
+            Some("The little yellow flower")
+        );
+    }
+
+    #[test]
+    fn test_xml_text() {
+        let xml =
+            "<lyric><contentts a=\"1\"><![CDATA[AB]]></contentts><content>CD</content></lyric>";
+        assert_eq!(xml_text(xml, "content"), Some("CD"));
+        assert_eq!(xml_text(xml, "contentts"), Some("AB"));
+        assert_eq!(xml_text(xml, "contentroma"), None);
+    }
+}
@@ -1,24 +1,52 @@
-use std::ffi::OsStr;
-use std::fs::read_to_string;
-use std::path::PathBuf;
+use std::ops::Range;
 use std::sync::mpsc::Receiver;
 use std::{sync::Arc, thread};
 
-use crate::fuo::FuoClient;
-use crate::lyric::Lyric;
-use crate::spotify::fetch_spotify_lyric;
+use crate::library::LyricLibrary;
+use crate::lyric::{progress, LyricCursor, LyricLine};
+use crate::provider::{fetch_lyric, TrackInfo};
+use crate::state::{track_key, State};
 use crate::Config;
 use eframe::egui::mutex::RwLock;
-use eframe::egui::TextBuffer;
 use log::{debug, error, info};
-use mpris::{Metadata, PlayerFinder};
+use mpris::{Player, PlayerFinder};
 use simsearch::SimSearch;
 use std::thread::{sleep, JoinHandle};
 use std::time::{Duration, Instant};
 
+/// Milliseconds between position updates of the serve loop.
+const TICK: u64 = 20;
+
+/// The line shown by the UI together with the playback position it was picked at.
+#[derive(Clone, Default)]
+pub struct CurrentLyric {
+    pub line: LyricLine,
+    pub position: u64,
+    /// Instrumental break following the line, see [`Lyric::gap_after`].
+    pub gap: Option<Range<u64>>,
+}
+
+impl CurrentLyric {
+    fn placeholder(content: &str) -> Self {
+        Self {
+            line: LyricLine::new(content),
+            ..Default::default()
+        }
+    }
+
+    /// Fraction of the instrumental break still to go, from `1.0` down to `0.0`, while
+    /// playback is inside it.
+    pub fn gap_remaining(&self) -> Option<f32> {
+        let gap = self.gap.as_ref().filter(|v| v.contains(&self.position))?;
+        Some(1.0 - progress(gap.start, gap.end, self.position))
+    }
+}
+
 pub enum Event {
     ChangePlayer(String),
     ToggleFuzzy,
+    /// Shift the current track's lyric earlier by this many milliseconds.
+    AdjustOffset(i64),
 }
 
 enum BreakLabel {
@@ -42,14 +70,19 @@ macro_rules! unwarp_or_continue {
 pub fn serve(
     mut config: Config,
     event_receiver: Receiver<Event>,
-) -> (JoinHandle<()>, Arc<RwLock<String>>) {
-    let _lock = Arc::new(RwLock::new("No lyric".to_owned()));
+) -> (JoinHandle<()>, Arc<RwLock<CurrentLyric>>) {
+    let _lock = Arc::new(RwLock::new(CurrentLyric::placeholder("No lyric")));
     let lock = _lock.clone();
+    let library = LyricLibrary::open(&config.lyric_dirs());
     (
         thread::spawn(move || 'finder: loop {
             let finder = unwarp_or_continue!(PlayerFinder::new(), 'finder);
+            let mut state = State::load();
+            // Key of the last track played, which offset adjustments apply to until the next
+            // track's metadata is read.
+            let mut track: Option<String> = None;
             'player: loop {
-                match handle_event(&event_receiver, &mut config) {
+                match handle_event(&event_receiver, &mut config, &mut state, track.as_deref()) {
                     BreakLabel::Player => {
                         break 'player;
                     }
@@ -60,13 +93,10 @@ pub fn serve(
                 info!("Attempting to find player: {}", config.player_name);
                 info!(
                     "Available players: {:?}",
-                    players
-                        .iter()
-                        .map(|v| v.bus_name_player_name_part())
-                        .collect::<Vec<&str>>()
+                    players.iter().map(player_name_part).collect::<Vec<&str>>()
                 );
                 for i in &players {
-                    let name = i.bus_name_player_name_part().to_owned();
+                    let name = player_name_part(i).to_owned();
                     engine.insert(i.identity(), &name);
                 }
                 let player = unwarp_or_continue!(
@@ -75,8 +105,8 @@ pub fn serve(
                             engine.search(
                                 config.player_name.as_str()
                             )
-                            .get(0).ok_or("No player"), 'player)), 'player);
-                info!("Selected player: {}", player.bus_name_player_name_part());
+                            .first().ok_or("No player"), 'player)), 'player);
+                info!("Selected player: {}", player_name_part(&player));
                 let metadata = unwarp_or_continue!(player.get_metadata(), 'player);
                 debug!("Metadata: {:?}", metadata);
                 info!(
@@ -84,34 +114,18 @@ pub fn serve(
                     unwarp_or_continue!(metadata.title().ok_or("Song doesn't have a title"), 'player)
                 );
                 error!("{}", config.player_name);
-                let lrc = if config.player_name == "feeluown" {
-                    if let Some(content) = FuoClient.lyric() {
-                        Lyric::from_str(&content)
-                    } else {
-                        Lyric::from_str("")
-                    }
-                } else if config.player_name == "spotify"
-                    && config.spotify_access_token.is_some()
-                    && config.spotify_client_token.is_some()
-                    && metadata.track_id().is_some()
-                {
-                    let trackid = metadata.track_id().unwrap().to_string();
-                    if trackid.contains("/com/spotify/track/") {
-                        let trackid = trackid.split('/').last().unwrap().to_string();
-                        debug!("Trackid: {}", trackid);
-                        fetch_spotify_lyric(
-                            config.spotify_access_token.as_ref().unwrap(),
-                            config.spotify_client_token.as_ref().unwrap(),
-                            Some(trackid),
-                        )
-                        .unwrap_or(Lyric::from_str(""))
-                    } else {
-                        Lyric::from_str("")
-                    }
-                } else {
-                    find_lyric(&metadata, &config.lyric_dir, config.fuzzy)
-                };
+                track = track_key(&metadata);
+                let info = TrackInfo::from_metadata(player_name_part(&player), &metadata);
+                let mut lrc = fetch_lyric(&config, &library, &info).unwrap_or_default();
                 //dbg!(&lrc);
+                let mut offset = track.as_deref().map(|v| state.offset(v)).unwrap_or(0);
+                if offset != 0 {
+                    info!("Using saved offset: {}ms", offset);
+                }
+                lrc.estimate_timing(metadata.length().map(|v| v.as_millis() as u64));
+                lrc.normalize(config.strip_credits);
+                let mut cursor = LyricCursor::default();
+                let mut shown: Option<Option<usize>> = None;
                 let mut count = 0;
                 let mut position = unwarp_or_continue!(player.get_position(), 'player);
                 let mut instant = Instant::now();
@@ -127,34 +141,46 @@ pub fn serve(
                         count = 0;
                     }
                     if count > 10 {
-                        match handle_event(&event_receiver, &mut config) {
+                        match handle_event(
+                            &event_receiver,
+                            &mut config,
+                            &mut state,
+                            track.as_deref(),
+                        ) {
                             BreakLabel::Player => {
                                 break 'player;
                             }
                             BreakLabel::None => {}
                         };
+                        offset = track.as_deref().map(|v| state.offset(v)).unwrap_or(0);
 
                         position = unwarp_or_continue!(player.get_position(), 'player);
                         instant = Instant::now();
                     }
-                    let pos = (position + instant.elapsed()).as_millis() as u64;
-                    let line = lrc
-                        .lines
-                        .iter()
-                        .filter(|v| v.begin <= pos)
-                        .map(|v| v.content.to_owned())
-                        .collect::<Vec<String>>();
-                    if let Some(line) = line.last() {
-                        {
-                            if !line.is_empty() {
-                                (*lock.write()) = line.to_owned();
-                            } else {
-                                (*lock.write()) = "No Lyric".to_owned();
-                            }
-                        }
+                    let pos = ((position + instant.elapsed()).as_millis() as u64)
+                        .saturating_add_signed(offset);
+                    let index = cursor.seek(&lrc, pos);
+                    if shown != Some(index) {
+                        shown = Some(index);
+                        let mut current = match index.map(|v| &lrc.lines[v]) {
+                            Some(line) if !line.content.is_empty() => CurrentLyric {
+                                line: line.clone(),
+                                ..Default::default()
+                            },
+                            _ => CurrentLyric::placeholder("No Lyric"),
+                        };
+                        current.position = pos;
+                        current.gap = lrc.gap_after(index, config.gap_threshold);
+                        (*lock.write()) = current;
+                    } else {
+                        lock.write().position = pos;
                     }
                     count += 1;
-                    sleep(Duration::from_millis(20));
+                    // Wake up right at the next line change instead of up to a tick late.
+                    let tick = cursor
+                        .next_change()
+                        .map_or(TICK, |v| v.saturating_sub(pos).clamp(1, TICK));
+                    sleep(Duration::from_millis(tick));
                 }
             }
         }),
@@ -162,64 +188,24 @@ pub fn serve(
     )
 }
 
-fn find_lyric(metadata: &Metadata, lyric_dir: &str, fuzzy: bool) -> Lyric {
-    let lyric_dir = lyric_dir.replace("~", dirs::home_dir().unwrap().to_string_lossy().as_str());
-    info!("Searching lyric in: {}", lyric_dir);
-    if let Some(url) = metadata.url() {
-        let path = PathBuf::from(url.replace("file://", ""));
-        if let Some(Some(file_stem)) = path.file_stem().map(|v| v.to_str()) {
-            if fuzzy {
-                let mut engine: SimSearch<PathBuf> = SimSearch::new();
-                std::fs::read_dir(lyric_dir)
-                    .unwrap()
-                    .map(|v| v.unwrap())
-                    .filter(|v| v.path().file_stem().is_some())
-                    .filter(|v| v.path().extension() == Some(OsStr::new("lrc")))
-                    .for_each(|v| {
-                        engine.insert(
-                            v.path(),
-                            v.path()
-                                .clone()
-                                .file_stem()
-                                .unwrap()
-                                .to_string_lossy()
-                                .as_str(),
-                        )
-                    });
-                let res = engine.search(file_stem);
-                if let Some(path) = res.get(0) {
-                    if let Ok(content) = read_to_string(path) {
-                        return Lyric::from_str(&content);
-                    }
-                }
-            } else {
-                if let Ok(content) = read_to_string(format!("{}/{}.lrc", lyric_dir, file_stem)) {
-                    return Lyric::from_str(&content);
-                }
-            }
-        }
-    }
-    if let Some(title) = metadata.title() {
-        let mut artist = String::new();
-        if let Some(artists) = metadata.artists() {
-            if let Some(_artist) = artists.first() {
-                artist = _artist.to_owned().to_owned()
-            }
-        }
-        let resp = find_lyric_online(title, artist.as_str());
-        match resp {
-            Ok(lyrics) => return Lyric::from_str(lyrics.as_str()),
-            Err(e) => {
-                log::error!("Error: {}", e);
-            }
-        };
-    }
-    Lyric::from_str("")
+/// The player part of the bus name without the instance suffix, e.g. `vlc` for
+/// `org.mpris.MediaPlayer2.vlc.instance123`.
+pub fn player_name_part(player: &Player) -> &str {
+    player
+        .bus_name_trimmed()
+        .split('.')
+        .next()
+        .unwrap_or_default()
 }
 
-fn handle_event(receiver: &Receiver<Event>, config: &mut Config) -> BreakLabel {
+fn handle_event(
+    receiver: &Receiver<Event>,
+    config: &mut Config,
+    state: &mut State,
+    track: Option<&str>,
+) -> BreakLabel {
     use BreakLabel::*;
-    if let Some(event) = receiver.try_recv().ok() {
+    if let Ok(event) = receiver.try_recv() {
         match event {
             Event::ChangePlayer(name) => {
                 info!("Received new player name: {}", name);
@@ -230,11 +216,13 @@ fn handle_event(receiver: &Receiver<Event>, config: &mut Config) -> BreakLabel {
                 config.fuzzy = !config.fuzzy;
                 return Player;
             }
+            Event::AdjustOffset(delta) => {
+                if let Some(track) = track {
+                    let offset = state.adjust_offset(track, delta);
+                    info!("Offset of {}: {}ms", track, offset);
+                }
+            }
         }
     }
     None
 }
-
-fn find_lyric_online(_title: &str, _artist: &str) -> Result<String, &'static str> {
-    Err("Not implemented")
-}
@@ -3,7 +3,7 @@ use std::time::{Duration, SystemTime};
 use log::debug;
 use serde_json::Value;
 
-use crate::lyric::{Lyric, LyricLine};
+use crate::lyric::{decode_entities, Lyric, LyricKind, LyricLine};
 
 struct ClientToken {
     expire: Duration,
@@ -24,15 +24,23 @@ pub fn fetch_spotify_lyric(token: &str, client_token: &str, id: Option<String>)
         .set("authorization", token)
         .set("client-token",client_token)
         .set("app-platform", "WebPlayer").call().ok()?.into_string().ok()?.as_str()).ok()? {
-        if let Value::Array(lines) = obj.get("lyrics")?.as_object()?.get("lines")? {
+        let lyrics = obj.get("lyrics")?.as_object()?;
+        let kind = match lyrics.get("syncType").and_then(|v| v.as_str()) {
+            Some("UNSYNCED") => LyricKind::Unsynced,
+            _ => LyricKind::Synced,
+        };
+        if let Value::Array(lines) = lyrics.get("lines")? {
             return Some(Lyric{
+                kind,
                 lines: lines.iter().map(|v| {
                     LyricLine{
-                        content: v.get("words").unwrap().as_str().unwrap().to_string(),
+                        content: decode_entities(v.get("words").unwrap().as_str().unwrap()),
                         begin: v.get("startTimeMs").unwrap().as_str().unwrap().parse().unwrap(),
                         end: v.get("endTimeMs").unwrap().as_str().unwrap().parse().unwrap(),
+                        ..Default::default()
                     }
-                }).collect()
+                }).collect(),
+                ..Default::default()
             }) ;
         }
     }
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::fs::{read_to_string, write};
+use std::path::PathBuf;
+
+use log::{error, info};
+use mpris::Metadata;
+use serde::{Deserialize, Serialize};
+
+/// Per-track data remembered across runs, stored next to other local application data.
+#[derive(Serialize, Deserialize, Default)]
+pub struct State {
+    /// Milliseconds to shift the lyric earlier by, keyed by [`track_key`].
+    #[serde(default)]
+    offsets: HashMap<String, i64>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl State {
+    pub fn load() -> Self {
+        let Some(path) = dirs::data_local_dir().map(|v| v.join("desktop_lyric").join("state.yaml"))
+        else {
+            return Self::default();
+        };
+        let mut state: Self = read_to_string(&path)
+            .ok()
+            .and_then(|v| serde_yaml::from_str(&v).ok())
+            .unwrap_or_default();
+        info!("Using state file: {}", path.to_string_lossy());
+        state.path = Some(path);
+        state
+    }
+
+    pub fn offset(&self, track: &str) -> i64 {
+        self.offsets.get(track).copied().unwrap_or(0)
+    }
+
+    pub fn adjust_offset(&mut self, track: &str, delta: i64) -> i64 {
+        let offset = self.offset(track) + delta;
+        if offset == 0 {
+            self.offsets.remove(track);
+        } else {
+            self.offsets.insert(track.to_owned(), offset);
+        }
+        self.save();
+        offset
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = std::fs::create_dir_all(path.parent().unwrap())
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_yaml::to_string(self).map_err(|e| e.to_string()))
+            .and_then(|v| write(path, v).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Failed to save state: {}", e);
+        }
+    }
+}
+
+/// Identifies a track by artist, title and album, falling back to the MPRIS trackid
+/// since many players only hand out per-session trackids.
+pub fn track_key(metadata: &Metadata) -> Option<String> {
+    if let Some(title) = metadata.title() {
+        let artists = metadata.artists().unwrap_or_default().join(", ");
+        let album = metadata.album_name().unwrap_or_default();
+        return Some(format!("{}/{}/{}", artists, title, album));
+    }
+    metadata.track_id().map(|v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn test_adjust_offset() {
+        let mut state = State::default();
+        assert_eq!(state.adjust_offset("a/b/c", 100), 100);
+        assert_eq!(state.adjust_offset("a/b/c", 100), 200);
+        assert_eq!(state.offset("a/b/c"), 200);
+        assert_eq!(state.adjust_offset("a/b/c", -200), 0);
+        assert!(state.offsets.is_empty());
+    }
+}
@@ -1,11 +1,19 @@
 use eframe::egui::{
-    CentralPanel, Context, Frame, Label, Margin, PointerButton, RichText, Rounding, Sense, Vec2,
-    ViewportCommand,
+    text::{CCursor, LayoutJob, TextFormat},
+    CentralPanel, Color32, Context, FontId, Frame, Margin, PointerButton, Pos2, Response, Rounding,
+    Sense, Ui, Vec2, ViewportCommand,
 };
 use log::{debug, info};
 
+use crate::serve::CurrentLyric;
+
 use super::DesktopLyricApp;
 
+/// Size of the translation row relative to `text_size`.
+const TRANSLATION_SCALE: f32 = 0.6;
+/// Dots of the instrumental break countdown, emptied from the right.
+const GAP_DOTS: usize = 3;
+
 impl DesktopLyricApp {
     pub fn lyric_ui(&mut self, ctx: &Context) {
         let resp = CentralPanel::default()
@@ -18,26 +26,10 @@ impl DesktopLyricApp {
             .show(ctx, |ui| {
                 let cur_lyric = { self.current_lyric.read().clone() };
                 let resp = if self.config.auto_resize {
-                    ui.add(
-                        Label::new(
-                            RichText::new(format!("{}", &cur_lyric))
-                                .color(self.config.text_color.color())
-                                .size(self.config.text_size),
-                        )
-                        .extend(),
-                    )
+                    self.lyric_label(ui, &cur_lyric)
                 } else {
-                    ui.centered_and_justified(|ui| {
-                        ui.add(
-                            Label::new(
-                                RichText::new(format!("{}", &cur_lyric))
-                                    .color(self.config.text_color.color())
-                                    .size(self.config.text_size),
-                            )
-                            .extend(),
-                        )
-                    })
-                    .inner
+                    ui.centered_and_justified(|ui| self.lyric_label(ui, &cur_lyric))
+                        .inner
                 };
 
                 let screen_rect = ctx.input(|v| v.screen_rect().max);
@@ -87,4 +79,81 @@ impl DesktopLyricApp {
             }
         }
     }
+
+    fn lyric_label(&self, ui: &mut Ui, cur_lyric: &CurrentLyric) -> Response {
+        if let Some(remaining) = cur_lyric.gap_remaining() {
+            return self.gap_label(ui, remaining);
+        }
+        let line = &cur_lyric.line;
+        let base_color = if self.config.karaoke {
+            self.config.unsung_color.color()
+        } else {
+            self.config.text_color.color()
+        };
+        let galley = ui.painter().layout_no_wrap(
+            line.content.clone(),
+            FontId::proportional(self.config.text_size),
+            Color32::PLACEHOLDER,
+        );
+        let translation = line
+            .translation
+            .as_ref()
+            .filter(|_| self.config.show_translation)
+            .map(|v| {
+                ui.painter().layout_no_wrap(
+                    v.clone(),
+                    FontId::proportional(self.config.text_size * TRANSLATION_SCALE),
+                    Color32::PLACEHOLDER,
+                )
+            });
+        let mut size = galley.size();
+        if let Some(translation) = &translation {
+            size.x = size.x.max(translation.size().x);
+            size.y += translation.size().y;
+        }
+        let (rect, resp) = ui.allocate_exact_size(size, Sense::hover());
+
+        let pos = Pos2::new(rect.center().x - galley.size().x / 2.0, rect.min.y);
+        ui.painter().galley(pos, galley.clone(), base_color);
+        if self.config.karaoke {
+            let sung = line.sung_chars(cur_lyric.position);
+            let index = sung.floor() as usize;
+            let start = galley.pos_from_ccursor(CCursor::new(index)).min.x;
+            let end = galley.pos_from_ccursor(CCursor::new(index + 1)).min.x;
+            let mut sung_rect = rect;
+            sung_rect.max.x = pos.x + start + (end - start) * sung.fract();
+            ui.painter()
+                .with_clip_rect(sung_rect.intersect(ui.clip_rect()))
+                .galley(pos, galley.clone(), self.config.sung_color.color());
+        }
+        if let Some(translation) = translation {
+            let pos = Pos2::new(
+                rect.center().x - translation.size().x / 2.0,
+                rect.min.y + galley.size().y,
+            );
+            ui.painter().galley(pos, translation, base_color);
+        }
+        resp
+    }
+
+    /// `♪ • • •` shown during an instrumental break. Each dot fades out in turn as the
+    /// next line approaches.
+    fn gap_label(&self, ui: &mut Ui, remaining: f32) -> Response {
+        let color = self.config.text_color.color();
+        let format = |color| TextFormat {
+            font_id: FontId::proportional(self.config.text_size),
+            color,
+            ..Default::default()
+        };
+        let mut job = LayoutJob::default();
+        job.append("♪", 0.0, format(color));
+        for i in 0..GAP_DOTS {
+            let fill = (remaining * GAP_DOTS as f32 - i as f32).clamp(0.0, 1.0);
+            job.append(" •", 0.0, format(color.gamma_multiply(fill)));
+        }
+        let galley = ui.painter().layout_job(job);
+        let (rect, resp) = ui.allocate_exact_size(galley.size(), Sense::hover());
+        ui.painter().galley(rect.min, galley, color);
+        resp
+    }
 }
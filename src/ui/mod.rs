@@ -7,16 +7,20 @@ use eframe::{
     egui::{self, mutex::RwLock, ViewportId},
     App,
 };
+use log::debug;
 use mpris::Player;
 
-use crate::{config::Config, serve::Event};
+use crate::{
+    config::Config,
+    serve::{CurrentLyric, Event},
+};
 
 mod lyric;
 mod run;
 mod settings;
 
 pub struct DesktopLyricApp {
-    pub current_lyric: Arc<RwLock<String>>,
+    pub current_lyric: Arc<RwLock<CurrentLyric>>,
     pub drag_mode: bool,
     pub settings_viewport_id: Option<ViewportId>,
     pub show_settings: bool,
@@ -26,15 +30,24 @@ pub struct DesktopLyricApp {
     pub config_path: PathBuf,
 }
 
+/// Milliseconds one key press or ctrl+scroll step moves the lyric by.
+const OFFSET_STEP: i64 = 100;
+
+impl DesktopLyricApp {
+    fn adjust_offset(&mut self, delta: i64) {
+        self.event_sender.try_send(Event::AdjustOffset(delta)).ok();
+    }
+}
+
 impl App for DesktopLyricApp {
     fn clear_color(&self, _visuals: &egui::Visuals) -> [f32; 4] {
         egui::Rgba::TRANSPARENT.to_array()
     }
 
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        self.lyric_ui(&ctx);
+        self.lyric_ui(ctx);
         if self.show_settings {
-            self.settings_ui(&ctx);
+            self.settings_ui(ctx);
         }
         std::thread::sleep(std::time::Duration::from_millis(1000 / 50));
         ctx.request_repaint();
@@ -43,6 +56,15 @@ impl App for DesktopLyricApp {
         use egui::Event::*;
         for i in raw_input.events.iter() {
             match i {
+                MouseWheel {
+                    delta, modifiers, ..
+                } if modifiers.ctrl => {
+                    if delta.y > 0.0 {
+                        self.adjust_offset(OFFSET_STEP);
+                    } else if delta.y < 0.0 {
+                        self.adjust_offset(-OFFSET_STEP);
+                    }
+                }
                 MouseWheel { delta, .. } => {
                     if delta.y > 0.0 {
                         self.config.text_size += 1.0;
@@ -52,24 +74,27 @@ impl App for DesktopLyricApp {
                 }
                 Key {
                     key,
-                    pressed,
+                    pressed: false,
                     modifiers,
                     ..
-                } => {
-                    if !*pressed {
-                        if *modifiers == egui::Modifiers::NONE {
-                            match key.name() {
-                                "S" => {
-                                    self.show_settings = true;
-                                }
-                                "P" => {
-                                    println!("{:?}", ctx.input(|v| v.screen_rect));
-                                }
-                                _ => {}
-                            }
-                        }
+                } if *modifiers == egui::Modifiers::NONE => match key.name() {
+                    "S" => {
+                        self.show_settings = true;
                     }
-                }
+                    "T" => {
+                        self.config.show_translation = !self.config.show_translation;
+                    }
+                    "OpenBracket" => {
+                        self.adjust_offset(-OFFSET_STEP);
+                    }
+                    "CloseBracket" => {
+                        self.adjust_offset(OFFSET_STEP);
+                    }
+                    "P" => {
+                        debug!("Screen rect: {:?}", ctx.input(|v| v.screen_rect));
+                    }
+                    _ => {}
+                },
                 _ => {}
             }
         }
@@ -3,7 +3,10 @@ use std::fs::write;
 use eframe::egui::{CentralPanel, ComboBox, Context, Slider, ViewportBuilder, ViewportId};
 use log::info;
 
-use crate::{font::setup_custom_fonts, serve::Event};
+use crate::{
+    font::setup_custom_fonts,
+    serve::{player_name_part, Event},
+};
 
 use super::DesktopLyricApp;
 
@@ -43,8 +46,8 @@ impl DesktopLyricApp {
                             for player in self.players.iter() {
                                 ui.selectable_value(
                                     &mut player_name,
-                                    player.bus_name_player_name_part().to_owned(),
-                                    player.bus_name_player_name_part(),
+                                    player_name_part(player).to_owned(),
+                                    player_name_part(player),
                                 );
                             }
                         });
@@ -69,7 +72,7 @@ impl DesktopLyricApp {
                 ui.horizontal(|ui| {
                     ui.label("Font");
                     ComboBox::from_id_source("font_combo_box")
-                        .selected_text(&self.config.font_name.clone().unwrap_or("".to_owned()))
+                        .selected_text(self.config.font_name.clone().unwrap_or("".to_owned()))
                         .show_ui(ui, |ui| {
                             for font in font_loader::system_fonts::query_all() {
                                 ui.selectable_value(&mut font_name, font.clone(), font.as_str());
@@ -89,6 +92,14 @@ impl DesktopLyricApp {
                     ui.label("Auto resize");
                     ui.checkbox(&mut self.config.auto_resize, "")
                 });
+                ui.horizontal(|ui| {
+                    ui.label("Karaoke");
+                    ui.checkbox(&mut self.config.karaoke, "")
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Show translation");
+                    ui.checkbox(&mut self.config.show_translation, "")
+                });
                 ui.horizontal(|ui| {
                     ui.label("Spotify access token");
                     let mut buf = self